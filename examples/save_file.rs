@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use smoldata::RawValue;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Item {
+    Sword { damage: u32 },
+    Potion(f32),
+    Key,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Save {
+    player: String,
+    position: (f32, f32),
+    inventory: Vec<Item>,
+    flags: HashMap<String, bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveHeader {
+    player: String,
+    position: (f32, f32),
+
+    // Not needed to show the save in a list, kept as raw bytes
+    inventory: RawValue,
+    flags: RawValue,
+}
+
+fn main() {
+    let save = Save {
+        player: "player".into(),
+        position: (10.5, -3.0),
+        inventory: vec![
+            Item::Sword { damage: 12 },
+            Item::Potion(0.5),
+            Item::Potion(1.0),
+            Item::Key,
+        ],
+        flags: HashMap::from_iter([("intro_done".into(), true), ("boss_defeated".into(), false)]),
+    };
+
+    let path = std::env::temp_dir().join("smoldata_example.sd");
+
+    smoldata::to_file(&save, &path).unwrap();
+    println!("Saved to {}", path.display());
+
+    let header: SaveHeader = smoldata::from_file(&path).unwrap();
+    println!("{} at {:?}", header.player, header.position);
+
+    let inventory: Vec<Item> = smoldata::from_raw(&header.inventory).unwrap();
+    println!("Inventory: {inventory:?}");
+
+    let loaded: Save = smoldata::from_file(&path).unwrap();
+    assert_eq!(loaded, save);
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smoldata::RawValue;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything read as a RawValue must be written and read back to the same bytes
+    let Ok(raw) = smoldata::from_bytes::<RawValue>(data) else {
+        return;
+    };
+    let bytes = smoldata::to_bytes(&raw).expect("valid RawValue must serialize");
+    let reread: RawValue = smoldata::from_bytes(&bytes).expect("serialized RawValue must deserialize");
+    assert_eq!(raw.bytes(), reread.bytes());
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[allow(unused)]
+enum Enum {
+    Unit,
+    Newtype(u32),
+    Tuple(i64, String),
+    Struct { name: String, values: Vec<f32> },
+}
+
+#[derive(Deserialize)]
+#[allow(unused)]
+struct Record {
+    id: u64,
+    name: String,
+    tags: Vec<String>,
+    children: HashMap<u32, Enum>,
+    parent: Option<Box<Record>>,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = smoldata::from_bytes::<Record>(data);
+    let _ = smoldata::from_bytes::<Vec<Enum>>(data);
+    let _ = smoldata::from_bytes::<HashMap<String, (char, bool, i8)>>(data);
+    let _ = smoldata::verify(data);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smoldata::value::Value;
+
+fuzz_target!(|value: Value| {
+    // Writing a value, reading it back and writing it again must produce the same bytes
+    let Ok(bytes) = smoldata::to_bytes(&value) else {
+        return;
+    };
+    let read: Value = smoldata::from_bytes(&bytes).expect("written Value must deserialize");
+    let rewritten = smoldata::to_bytes(&read).expect("read Value must serialize");
+    assert_eq!(bytes, rewritten);
+});
@@ -0,0 +1,112 @@
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    net::{FrameCodec, FrameError},
+    varint::VarIntReadError,
+};
+
+/// Writer of framed documents into an `AsyncWrite`, in the format of [`FrameCodec`].<br>
+/// Each value is serialized into a reused buffer before being written, so memory use is bounded by the largest value, not the whole stream
+pub struct AsyncWriter<W> {
+    writer: W,
+    codec: FrameCodec,
+    buf: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            codec: FrameCodec::new(),
+            buf: vec![],
+        }
+    }
+
+    /// Set max size of a single frame, see [`FrameCodec::with_max_size`]
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.codec = self.codec.with_max_size(max_size);
+        self
+    }
+
+    /// Serialize a value and write it as a single frame
+    pub async fn write<T: Serialize>(&mut self, data: &T) -> Result<(), FrameError> {
+        self.buf.clear();
+        self.codec.encode(data, &mut self.buf)?;
+        self.writer.write_all(&self.buf).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), FrameError> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reader of framed documents from an `AsyncRead`, in the format of [`FrameCodec`].<br>
+/// Frame data is read into a reused buffer and deserialized once complete
+pub struct AsyncReader<R> {
+    reader: R,
+    codec: FrameCodec,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            codec: FrameCodec::new(),
+            buf: vec![],
+        }
+    }
+
+    /// Set max size of a single frame, see [`FrameCodec::with_max_size`]
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.codec = self.codec.with_max_size(max_size);
+        self
+    }
+
+    /// Read a single frame and deserialize it.<br>
+    /// Returns None if the reader ended before the next frame
+    pub async fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>, FrameError> {
+        let Some(len) = self.read_length().await? else {
+            return Ok(None);
+        };
+        self.codec.check_len(len)?;
+
+        self.buf.clear();
+        let read = (&mut self.reader).take(len as u64).read_to_end(&mut self.buf).await?;
+        if read != len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(Some(crate::from_bytes(&self.buf)?))
+    }
+
+    /// Read varint frame length, None on EOF before its first byte.<br>
+    /// Bytes are collected until the last one and decoded by [`crate::varint::read_length`], so overflow checks match the sync reader
+    async fn read_length(&mut self) -> Result<Option<usize>, FrameError> {
+        let mut bytes = [0u8; 10];
+        for i in 0..bytes.len() {
+            if self.reader.read(&mut bytes[i..=i]).await? == 0 {
+                if i == 0 {
+                    return Ok(None);
+                }
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+
+            if bytes[i] & 0x80 == 0 {
+                let len = crate::varint::read_length(&bytes[..=i]).map_err(FrameError::InvalidLength)?;
+                return Ok(Some(len));
+            }
+        }
+        Err(FrameError::InvalidLength(VarIntReadError::ValueTooBig))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write as _},
+    process::ExitCode,
+};
+
+use smoldata::{
+    debug::{AnnotatedDump, HexDump, VarintDump},
+    value::{Value, VariantData},
+    Deserializer,
+};
+
+const USAGE: &str = "\
+Usage: sd-tool <command> [args]
+
+Commands:
+  dump <file>                      Print the document as a tree
+  hexdump [--annotated] <file>     Print a hexdump of the file, annotated with tags
+  varints <file> <offset> [count]  Print bytes at a hex offset as varints, 16 by default
+  to-json <file>                   Print the document as JSON
+  from-json <json file> <file>     Write JSON as a document
+  stats <file>                     Print tag counts, string map usage and largest fields";
+
+type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let res = match args.as_slice() {
+        ["dump", file] => dump(file),
+        ["hexdump", file] => hexdump(file, false),
+        ["hexdump", "--annotated", file] | ["hexdump", file, "--annotated"] => hexdump(file, true),
+        ["varints", file, offset] => varints(file, offset, "16"),
+        ["varints", file, offset, count] => varints(file, offset, count),
+        ["to-json", file] => to_json(file),
+        ["from-json", json, file] => from_json(json, file),
+        ["stats", file] => stats(file),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn dump(file: &str) -> Result {
+    let data = fs::read(file)?;
+    let value = Value::read(&mut Deserializer::new(data.as_slice())?)?;
+
+    let mut tree = String::new();
+    write_tree(&mut tree, &value, 0)?;
+    io::stdout().write_all(tree.as_bytes())?;
+    Ok(())
+}
+
+fn hexdump(file: &str, annotated: bool) -> Result {
+    let data = fs::read(file)?;
+    match annotated {
+        true => print!("{}", AnnotatedDump::new(&data)),
+        false => print!("{}", HexDump(&data)),
+    }
+    Ok(())
+}
+
+fn varints(file: &str, offset: &str, count: &str) -> Result {
+    let data = fs::read(file)?;
+    let offset = usize::from_str_radix(offset.trim_start_matches("0x"), 16)?;
+    if offset > data.len() {
+        return Err(format!("offset {offset:x} is past the end of the file").into());
+    }
+    print!("{}", VarintDump::new(&data, offset).with_limit(count.parse()?));
+    Ok(())
+}
+
+fn to_json(file: &str) -> Result {
+    let json = smoldata::convert::to_json_value(io::BufReader::new(fs::File::open(file)?))?;
+    println!("{}", serde_json::to_string_pretty(&json)?);
+    Ok(())
+}
+
+fn from_json(json: &str, file: &str) -> Result {
+    let json: serde_json::Value = serde_json::from_slice(&fs::read(json)?)?;
+    let mut writer = io::BufWriter::new(fs::File::create(file)?);
+    smoldata::convert::from_json_value(&json, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn stats(file: &str) -> Result {
+    let data = fs::read(file)?;
+    println!("{} bytes", data.len());
+
+    let mut de = Deserializer::new(data.as_slice())?;
+    Value::read(&mut de)?;
+    let strings = de.string_map_stats();
+    println!("\nString map: {} strings, {} bytes", strings.count, strings.bytes);
+
+    println!("\nTags:");
+    let mut counts: Vec<_> = AnnotatedDump::new(&data).tag_counts().into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    for (tag, count) in counts {
+        println!("  {tag}: {count}");
+    }
+
+    println!("\nLargest fields:");
+    for (path, size) in smoldata::measure::measure(&data)?.heaviest(10) {
+        let path = if path.is_empty() { "(root)" } else { path };
+        println!("  {path}: {} values, {} bytes", size.count, size.bytes);
+    }
+    Ok(())
+}
+
+/// Write a value with each nested value on its own line, indented by its depth
+fn write_tree(out: &mut String, value: &Value, depth: usize) -> std::fmt::Result {
+    match value {
+        Value::Option(Some(v)) => {
+            writeln!(out, "Some")?;
+            write_child(out, None, v, depth)
+        }
+        Value::Newtype(v) => {
+            writeln!(out, "Newtype")?;
+            write_child(out, None, v, depth)
+        }
+        Value::Struct(fields) => {
+            writeln!(out, "Struct")?;
+            fields.iter().try_for_each(|(name, v)| write_child(out, Some(name), v, depth))
+        }
+        Value::Variant(name, data) => match data {
+            VariantData::Unit => writeln!(out, "{name}"),
+            VariantData::Newtype(v) => {
+                writeln!(out, "{name}")?;
+                write_child(out, None, v, depth)
+            }
+            VariantData::Tuple(values) => {
+                writeln!(out, "{name}")?;
+                write_elements(out, values, depth)
+            }
+            VariantData::Struct(fields) => {
+                writeln!(out, "{name}")?;
+                fields.iter().try_for_each(|(name, v)| write_child(out, Some(name), v, depth))
+            }
+        },
+        Value::Array(values) => {
+            writeln!(out, "Array ({})", values.len())?;
+            write_elements(out, values, depth)
+        }
+        Value::Tuple(values) => {
+            writeln!(out, "Tuple")?;
+            write_elements(out, values, depth)
+        }
+        Value::Map(entries) => {
+            writeln!(out, "Map ({})", entries.len())?;
+            entries
+                .iter()
+                .try_for_each(|(k, v)| write_child(out, Some(&k.to_string()), v, depth))
+        }
+        scalar => writeln!(out, "{scalar}"),
+    }
+}
+
+fn write_elements(out: &mut String, values: &[Value], depth: usize) -> std::fmt::Result {
+    values
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, v)| write_child(out, Some(&format!("[{i}]")), v, depth))
+}
+
+fn write_child(out: &mut String, name: Option<&str>, value: &Value, depth: usize) -> std::fmt::Result {
+    write!(out, "{:indent$}", "", indent = (depth + 1) * 2)?;
+    if let Some(name) = name {
+        write!(out, "{name}: ")?;
+    }
+    write_tree(out, value, depth + 1)
+}
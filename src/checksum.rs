@@ -0,0 +1,65 @@
+use std::io;
+
+use crate::de::DeserializeError;
+
+/// Length of the checksum trailer following document data
+pub(crate) const TRAILER_LEN: usize = 4;
+
+/// Writer computing a CRC32 checksum of data written into it, see `Serializer::new_checksummed`.<br>
+/// Checksum is written as a trailer when it's finished or dropped, use `finish` to handle errors and get the inner writer back
+pub struct ChecksumWriter<W: io::Write> {
+    writer: Option<W>,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: io::Write> ChecksumWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Some(writer),
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+
+    /// Write the checksum trailer, returning the inner writer
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.write_trailer()?;
+        Ok(self.writer.take().expect("writer is only taken when finishing"))
+    }
+
+    fn write_trailer(&mut self) -> Result<(), io::Error> {
+        let checksum = self.hasher.clone().finalize();
+        self.writer().write_all(&checksum.to_le_bytes())
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer is only taken when finishing")
+    }
+}
+
+impl<W: io::Write> io::Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer().write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl<W: io::Write> Drop for ChecksumWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_trailer();
+        }
+    }
+}
+
+pub(crate) fn check(computed: u32, trailer: [u8; TRAILER_LEN]) -> Result<(), DeserializeError> {
+    let stored = u32::from_le_bytes(trailer);
+    match stored == computed {
+        true => Ok(()),
+        false => Err(DeserializeError::ChecksumMismatch { stored, computed }),
+    }
+}
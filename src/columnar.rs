@@ -0,0 +1,329 @@
+use std::{collections::BTreeMap, io, marker::PhantomData, slice};
+
+use serde::{
+    de::{self, DeserializeOwned, IntoDeserializer},
+    ser::{self, SerializeMap, SerializeSeq, SerializeTuple},
+    Deserialize, Serialize,
+};
+
+use crate::{
+    de::{DeserializeError, Deserializer},
+    raw::{reject_serialize, RawValue, SerdeSerializerStub},
+    tag::TypeTag,
+    varint,
+};
+
+/// Vec of structs, stored as an array of values per field instead of a struct per element.<br>
+/// Field names are written once instead of once per element, which makes large tables of records smaller.<br>
+/// All elements must serialize as structs with the same fields in the same order,
+/// so fields skipped with `skip_serializing_if` can't be used.<br>
+/// Can be used on Vec fields directly with `#[serde(with = "smoldata::columnar")]`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Columnar<T>(pub T);
+
+pub fn serialize<T: Serialize, S: serde::Serializer>(value: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut names = vec![];
+    if let Some(first) = value.first() {
+        first.serialize(FieldNames {
+            names: &mut names,
+            _error: PhantomData,
+        })?;
+    }
+
+    let mut tuple = serializer.serialize_tuple(2)?;
+    tuple.serialize_element(&value.len())?;
+    tuple.serialize_element(&Columns {
+        rows: value,
+        names: &names,
+    })?;
+    tuple.end()
+}
+
+pub fn deserialize<'de, T: DeserializeOwned, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    let (len, columns): (usize, BTreeMap<String, RawValue>) = Deserialize::deserialize(deserializer)?;
+
+    // Each column is read one value at a time, as rows are deserialized
+    let mut readers = Vec::with_capacity(columns.len());
+    for (name, column) in &columns {
+        let mut de = column.create_deserializer();
+        let column_len = read_column_len(&mut de).map_err(|e| de::Error::custom(e.in_field(name)))?;
+        if column_len != len {
+            return Err(de::Error::custom(format_args!(
+                "Column \"{name}\" has {column_len} values, expected {len}"
+            )));
+        }
+        readers.push((name.as_str(), de));
+    }
+
+    (0..len)
+        .map(|_| {
+            T::deserialize(RowDeserializer {
+                columns: &mut readers,
+            })
+            .map_err(de::Error::custom)
+        })
+        .collect()
+}
+
+impl<T: Serialize> Serialize for Columnar<Vec<T>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Columnar<Vec<T>> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserialize(deserializer).map(Columnar)
+    }
+}
+
+fn not_a_struct<E: ser::Error>() -> E {
+    E::custom("Columnar elements must be structs")
+}
+
+fn fields_mismatch<E: ser::Error>() -> E {
+    E::custom("Columnar elements must have the same fields in the same order")
+}
+
+/// Map of field names to their columns
+struct Columns<'a, T> {
+    rows: &'a [T],
+    names: &'a [&'static str],
+}
+
+impl<T: Serialize> Serialize for Columns<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.names.len()))?;
+        for (field, name) in self.names.iter().enumerate() {
+            map.serialize_entry(name, &Column {
+                rows: self.rows,
+                names: self.names,
+                field,
+            })?;
+        }
+        map.end()
+    }
+}
+
+/// Sequence of values of a single field of every row
+struct Column<'a, T> {
+    rows: &'a [T],
+    names: &'a [&'static str],
+    field: usize,
+}
+
+impl<T: Serialize> Serialize for Column<'_, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.rows.len()))?;
+        for row in self.rows {
+            row.serialize(FieldPicker {
+                seq: &mut seq,
+                names: self.names,
+                field: self.field,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+/// Serializer collecting field names of a struct
+struct FieldNames<'a, E> {
+    names: &'a mut Vec<&'static str>,
+    _error: PhantomData<E>,
+}
+
+impl<'a, E: ser::Error> serde::Serializer for FieldNames<'a, E> {
+    type Ok = ();
+    type Error = E;
+
+    type SerializeSeq = SerdeSerializerStub<(), E>;
+    type SerializeTuple = SerdeSerializerStub<(), E>;
+    type SerializeTupleStruct = SerdeSerializerStub<(), E>;
+    type SerializeTupleVariant = SerdeSerializerStub<(), E>;
+    type SerializeMap = SerdeSerializerStub<(), E>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = SerdeSerializerStub<(), E>;
+
+    reject_serialize!(not_a_struct();
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str bytes none some unit unit_struct unit_variant
+        newtype_struct newtype_variant seq tuple tuple_struct tuple_variant map struct_variant
+    );
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self)
+    }
+}
+
+impl<E: ser::Error> ser::SerializeStruct for FieldNames<'_, E> {
+    type Ok = ();
+    type Error = E;
+
+    fn serialize_field<T>(&mut self, key: &'static str, _value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.names.push(key);
+        Ok(())
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        Err(skipped_field(key))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+fn skipped_field<E: ser::Error>(key: &str) -> E {
+    E::custom(format_args!("Columnar elements can't skip fields, field \"{key}\" was skipped"))
+}
+
+/// Serializer writing a single field of a struct into its column
+struct FieldPicker<'a, Q> {
+    seq: &'a mut Q,
+    names: &'a [&'static str],
+    field: usize,
+}
+
+struct PickerFields<'a, Q> {
+    picker: FieldPicker<'a, Q>,
+    index: usize,
+}
+
+impl<'a, Q: SerializeSeq> serde::Serializer for FieldPicker<'a, Q> {
+    type Ok = ();
+    type Error = Q::Error;
+
+    type SerializeSeq = SerdeSerializerStub<(), Q::Error>;
+    type SerializeTuple = SerdeSerializerStub<(), Q::Error>;
+    type SerializeTupleStruct = SerdeSerializerStub<(), Q::Error>;
+    type SerializeTupleVariant = SerdeSerializerStub<(), Q::Error>;
+    type SerializeMap = SerdeSerializerStub<(), Q::Error>;
+    type SerializeStruct = PickerFields<'a, Q>;
+    type SerializeStructVariant = SerdeSerializerStub<(), Q::Error>;
+
+    reject_serialize!(not_a_struct();
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str bytes none some unit unit_struct unit_variant
+        newtype_struct newtype_variant seq tuple tuple_struct tuple_variant map struct_variant
+    );
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(PickerFields {
+            picker: self,
+            index: 0,
+        })
+    }
+}
+
+impl<Q: SerializeSeq> ser::SerializeStruct for PickerFields<'_, Q> {
+    type Ok = ();
+    type Error = Q::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.picker.names.get(self.index) != Some(&key) {
+            return Err(fields_mismatch());
+        }
+        if self.index == self.picker.field {
+            self.picker.seq.serialize_element(value)?;
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        Err(skipped_field(key))
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        if self.index != self.picker.names.len() {
+            return Err(fields_mismatch());
+        }
+        Ok(())
+    }
+}
+
+type ColumnReader<'a> = Deserializer<io::Cursor<&'a [u8]>>;
+
+/// Read the length of a column sequence, leaving the reader at its first value
+fn read_column_len(de: &mut ColumnReader) -> Result<usize, DeserializeError> {
+    match de.read_tag()? {
+        TypeTag::Seq { has_length: true } => Ok(varint::read_length(&mut de.reader)?),
+        tag => Err(DeserializeError::Expected("sequence with length", tag.into())),
+    }
+}
+
+/// Deserializer presenting the next value of every column as a struct
+struct RowDeserializer<'a, 'b> {
+    columns: &'a mut [(&'b str, ColumnReader<'b>)],
+}
+
+impl<'de> serde::Deserializer<'de> for RowDeserializer<'_, '_> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_map(RowAccess {
+            columns: self.columns.iter_mut(),
+            value: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RowAccess<'a, 'b> {
+    columns: slice::IterMut<'a, (&'b str, ColumnReader<'b>)>,
+
+    /// Column of the last read key
+    value: Option<&'a mut (&'b str, ColumnReader<'b>)>,
+}
+
+impl<'de> de::MapAccess<'de> for RowAccess<'_, '_> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let Some(column) = self.columns.next() else {
+            return Ok(None);
+        };
+        let key = seed.deserialize(IntoDeserializer::<Self::Error>::into_deserializer(column.0))?;
+        self.value = Some(column);
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let (name, de) = self.value.take().ok_or(DeserializeError::TriedValedGotKey)?;
+        seed.deserialize(&mut *de).map_err(|e| e.in_field(name))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.columns.len())
+    }
+}
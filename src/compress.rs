@@ -0,0 +1,157 @@
+use std::io;
+#[cfg(feature = "zstd")]
+use std::sync::Arc;
+
+#[cfg(feature = "zstd")]
+pub(crate) const COMPRESSION_ZSTD: u8 = 1;
+#[cfg(feature = "lz4")]
+pub(crate) const COMPRESSION_LZ4: u8 = 2;
+
+/// Compression of document data following the header, see `Serializer::new_compressed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// zstd with a compression level, 0 being zstd's default level
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+
+    /// zstd with a compression level, compressing on `workers` background threads.<br>
+    /// Data is split into jobs compressed in parallel, which speeds up writing large documents.
+    /// Decompression is not parallel, output is read the same as `Zstd`
+    #[cfg(feature = "parallel")]
+    ZstdParallel { level: i32, workers: u32 },
+
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// Compression id written into the header
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => COMPRESSION_ZSTD,
+            #[cfg(feature = "parallel")]
+            Self::ZstdParallel { .. } => COMPRESSION_ZSTD,
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => COMPRESSION_LZ4,
+        }
+    }
+}
+
+/// External zstd dictionary, such as one trained with `zstd --train` on many similar documents, and the id documents reference it by.<br>
+/// Compresses small documents much better than zstd alone, but they can only be read with the same dictionary,
+/// see `Serializer::new_compressed_with_dictionary`
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone)]
+pub struct ZstdDictionary {
+    id: u32,
+    data: Arc<[u8]>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdDictionary {
+    pub fn new(id: u32, data: impl Into<Arc<[u8]>>) -> Self {
+        Self { id, data: data.into() }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Writer compressing data written into it.<br>
+/// Compression is finished when it's dropped, use `finish` to handle errors and get the inner writer back
+pub struct CompressedWriter<W: io::Write> {
+    encoder: Option<Encoder<W>>,
+}
+
+enum Encoder<W: io::Write> {
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+}
+
+impl<W: io::Write> CompressedWriter<W> {
+    pub fn new(writer: W, compression: Compression) -> Result<Self, io::Error> {
+        let encoder = match compression {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, level)?),
+            #[cfg(feature = "parallel")]
+            Compression::ZstdParallel { level, workers } => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level)?;
+                encoder.multithread(workers)?;
+                Encoder::Zstd(encoder)
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Encoder::Lz4(lz4_flex::frame::FrameEncoder::new(writer)),
+        };
+        Ok(Self {
+            encoder: Some(encoder),
+        })
+    }
+
+    /// Writer compressing data with zstd and an external dictionary
+    #[cfg(feature = "zstd")]
+    pub fn with_zstd_dictionary(writer: W, level: i32, dictionary: &ZstdDictionary) -> Result<Self, io::Error> {
+        let encoder = zstd::stream::write::Encoder::with_dictionary(writer, level, dictionary.data())?;
+        Ok(Self {
+            encoder: Some(Encoder::Zstd(encoder)),
+        })
+    }
+
+    /// Finish compression, writing remaining compressed data into the inner writer
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish(),
+            None => unreachable!("encoder is only taken when finishing"),
+        }
+    }
+
+    fn encoder(&mut self) -> &mut Encoder<W> {
+        self.encoder.as_mut().expect("encoder is only taken when finishing")
+    }
+}
+
+impl<W: io::Write> Encoder<W> {
+    fn finish(self) -> Result<W, io::Error> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Self::Zstd(encoder) => encoder.finish(),
+            #[cfg(feature = "lz4")]
+            Self::Lz4(encoder) => Ok(encoder.finish()?),
+        }
+    }
+}
+
+impl<W: io::Write> io::Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.encoder() {
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.encoder() {
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl<W: io::Write> Drop for CompressedWriter<W> {
+    fn drop(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            let _ = encoder.finish();
+        }
+    }
+}
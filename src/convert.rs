@@ -0,0 +1,99 @@
+use std::io;
+
+use serde_json::{Map, Number, Value as Json};
+
+use crate::{
+    de::{DeserializeError, Deserializer},
+    ser::SerializeError,
+    value::{Value, VariantData},
+};
+
+/// Read a document and convert it to JSON, for inspecting binary data or passing it to web tooling.<br>
+/// Conversion follows serde_json conventions: options are `null` or the value, newtypes are their value,
+/// unit variants are strings and other variants are objects with a single field named after the variant.<br>
+/// Map keys that aren't strings are written as strings, maps with keys that can't be are written as arrays of `[key, value]` pairs.
+/// Non-finite floats become `null` and integers not fitting into 64 bits become strings
+pub fn to_json_value<R: io::Read>(reader: R) -> Result<Json, DeserializeError> {
+    let mut de = Deserializer::new(reader)?;
+    Ok(value_to_json(Value::read(&mut de)?))
+}
+
+/// Write JSON as a document.<br>
+/// Objects are written as maps and numbers as `u64`, `i64` or `f64`, so they can be read into types with the same JSON layout
+pub fn from_json_value<W: io::Write>(json: &Json, writer: W) -> Result<(), SerializeError> {
+    crate::to_writer(json, writer)
+}
+
+/// Convert a value to JSON, see [`to_json_value`]
+pub fn value_to_json(value: Value) -> Json {
+    match value {
+        Value::Unit => Json::Null,
+        Value::Bool(v) => Json::Bool(v),
+        Value::Int(v) => match i64::try_from(v) {
+            Ok(v) => v.into(),
+            Err(_) => v.to_string().into(),
+        },
+        Value::UInt(v) => match u64::try_from(v) {
+            Ok(v) => v.into(),
+            Err(_) => v.to_string().into(),
+        },
+        Value::Float(v) => Number::from_f64(v).map_or(Json::Null, Json::Number),
+        Value::Char(v) => v.to_string().into(),
+        Value::Str(v) => v.into(),
+        Value::Bytes(v) => v.into(),
+        Value::Option(None) => Json::Null,
+        Value::Option(Some(v)) | Value::Newtype(v) => value_to_json(*v),
+        Value::Struct(fields) => fields_to_json(fields),
+        Value::Variant(name, VariantData::Unit) => name.into(),
+        Value::Variant(name, data) => {
+            let data = match data {
+                VariantData::Unit => unreachable!(),
+                VariantData::Newtype(v) => value_to_json(*v),
+                VariantData::Tuple(values) => values_to_json(values),
+                VariantData::Struct(fields) => fields_to_json(fields),
+            };
+            Json::Object(Map::from_iter([(name, data)]))
+        }
+        Value::Array(values) | Value::Tuple(values) => values_to_json(values),
+        Value::Map(entries) => {
+            if entries.iter().all(|(k, _)| json_key(k).is_some()) {
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (json_key(&k).unwrap(), value_to_json(v)))
+                    .collect::<Map<_, _>>()
+                    .into()
+            } else {
+                entries
+                    .into_iter()
+                    .map(|(k, v)| Json::Array(vec![value_to_json(k), value_to_json(v)]))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn values_to_json(values: Vec<Value>) -> Json {
+    values.into_iter().map(value_to_json).collect()
+}
+
+fn fields_to_json(fields: Vec<(String, Value)>) -> Json {
+    fields
+        .into_iter()
+        .map(|(k, v)| (k, value_to_json(v)))
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+/// Map key as a JSON object key, if it's a string or can be written as one
+fn json_key(key: &Value) -> Option<String> {
+    match key {
+        Value::Str(v) => Some(v.clone()),
+        Value::Char(v) => Some(v.to_string()),
+        Value::Bool(v) => Some(v.to_string()),
+        Value::Int(v) => Some(v.to_string()),
+        Value::UInt(v) => Some(v.to_string()),
+        Value::Newtype(v) => json_key(v),
+        Value::Variant(name, VariantData::Unit) => Some(name.clone()),
+        _ => None,
+    }
+}
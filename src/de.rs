@@ -1,12 +1,40 @@
-use std::{collections::BTreeMap, fmt, io, ops::Deref, slice, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    io::{self, Read},
+    marker::PhantomData,
+    ops::Deref,
+    slice,
+    sync::Arc,
+};
+
+use serde::de::DeserializeOwned;
 
 use crate::{
-    tag::{FlatTypeTag, FloatWidth, IntWidth, OptionTag, StrNewIndex, StructType, TypeTag},
-    varint, FORMAT_VERSION, MAGIC_HEADER,
+    profile::{Profile, Timer},
+    tag::{FlatTypeTag, FloatWidth, IntWidth, KeyType, OptionTag, StrNewIndex, StructType, TypeTag, RESERVED_SKIPPABLE_TAGS},
+    varint, FloatPolicy, FORMAT_VERSION, MAGIC_HEADER,
 };
 
 // TODO: care about what deserializer wants, not just deserializing any
 
+/// Largest amount of elements or bytes allocated upfront from a length read from the data
+const MAX_PREALLOCATION: usize = 4096;
+
+/// How struct fields that appear more than once in the data are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateFieldPolicy {
+    /// Pass every field to the visitor, derived `Deserialize` implementations error on duplicates
+    #[default]
+    Error,
+
+    /// Keep the first value of a field, ignore later ones
+    FirstWins,
+
+    /// Keep the last value of a field, ignore earlier ones
+    LastWins,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DeserializeError {
     #[error(transparent)]
@@ -55,10 +83,67 @@ pub enum DeserializeError {
     #[error("Attempted to deserialize map value but got key")]
     TriedValedGotKey,
 
+    #[error("Read 128-bit integer, but their support is disabled")]
+    Int128Disabled,
+
+    #[error("Read non-finite float at \"{0}\"")]
+    NonFiniteFloat(String),
+
+    #[error("Found data after the end of the document")]
+    TrailingData,
+
+    #[error("Read tag {0} reserved for newer format versions, data may be written by a newer version of the library")]
+    ReservedTag(u8),
+
+    #[error("Read external byte array, but no blob loader is set")]
+    NoBlobLoader,
+
+    #[error("Read unknown enum variant \"{variant}\", expected one of: {}", .expected.join(", "))]
+    UnknownVariant {
+        variant: String,
+        expected: &'static [&'static str],
+    },
+
+    #[error("Reading aborted by the continue check")]
+    TimedOut,
+
+    #[error("Checksum mismatch, stored {stored:08x}, computed {computed:08x}, data is corrupted")]
+    ChecksumMismatch { stored: u32, computed: u32 },
+
+    #[error("Expected byte array of {expected} bytes, read one of {read} bytes")]
+    LengthMismatch { expected: usize, read: usize },
+
     #[error("{0}")]
     Custom(String),
 }
 
+impl DeserializeError {
+    /// Prepend field name to the path of errors that contain one
+    pub(crate) fn in_field(self, field: &str) -> Self {
+        self.in_path(|| field.into())
+    }
+
+    /// Prepend sequence element index to the path of errors that contain one
+    pub(crate) fn in_index(self, index: usize) -> Self {
+        self.in_path(|| format!("[{index}]"))
+    }
+
+    /// Prepend map key to the path of errors that contain one, keys other than strings are written as `[..]`
+    pub(crate) fn in_key(self, key: Option<&str>) -> Self {
+        self.in_path(|| match key {
+            Some(key) => format!("[{key:?}]"),
+            None => "[..]".into(),
+        })
+    }
+
+    fn in_path(self, segment: impl FnOnce() -> String) -> Self {
+        match self {
+            Self::NonFiniteFloat(path) => Self::NonFiniteFloat(crate::prepend_path(&segment(), &path)),
+            e => e,
+        }
+    }
+}
+
 impl serde::de::Error for DeserializeError {
     fn custom<T>(msg: T) -> Self
     where
@@ -66,6 +151,13 @@ impl serde::de::Error for DeserializeError {
     {
         Self::Custom(msg.to_string())
     }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        Self::UnknownVariant {
+            variant: variant.into(),
+            expected,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -78,6 +170,15 @@ pub enum DeserializerInitError {
 
     #[error("Unsupported format version {0}")]
     UnsupportedVersion(u8),
+
+    #[error("Unsupported compression {0}, compression features may be disabled")]
+    UnsupportedCompression(u8),
+
+    #[error("Document has a checksum, but the checksum feature is disabled")]
+    UnsupportedChecksum,
+
+    #[error("Document is compressed with zstd dictionary {0}, which wasn't given")]
+    UnknownDictionary(u32),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -87,6 +188,19 @@ pub enum ReadTagError {
 
     #[error("Read invalid tag {0}")]
     InvalidTag(u8),
+
+    #[error("Read tag {0} reserved for newer format versions")]
+    ReservedTag(u8),
+
+    #[error("Reading aborted by the continue check")]
+    TimedOut,
+
+    #[error("VarInt reading error")]
+    ReadVarint(
+        #[from]
+        #[source]
+        varint::VarIntReadError,
+    ),
 }
 
 impl From<ReadTagError> for DeserializeError {
@@ -94,6 +208,9 @@ impl From<ReadTagError> for DeserializeError {
         match val {
             ReadTagError::IOError(error) => Self::IOError(error),
             ReadTagError::InvalidTag(i) => Self::InvalidTag(i),
+            ReadTagError::ReservedTag(i) => Self::ReservedTag(i),
+            ReadTagError::ReadVarint(e) => Self::ReadVarint(e),
+            ReadTagError::TimedOut => Self::TimedOut,
         }
     }
 }
@@ -128,20 +245,180 @@ impl From<ReadStrError> for DeserializeError {
     }
 }
 
+/// Callback invoked with every string the deserializer decodes from the data,
+/// returned string is used in its place
+pub type StringHook = dyn FnMut(Arc<str>) -> Arc<str> + Send;
+
+/// Callback checked before reading every value, reading is aborted with `TimedOut` once it returns false
+pub type ContinueCheck = dyn FnMut() -> bool + Send;
+
+/// Reader wrapper tracking amount of bytes read.<br>
+/// For compressed documents, position is of decompressed data
+pub(crate) struct PositionReader<R: io::Read> {
+    reader: SourceReader<R>,
+    position: u64,
+    #[cfg(feature = "checksum")]
+    checksum: Option<crc32fast::Hasher>,
+}
+
+/// Reader of document data, decompressing it if the document is compressed
+enum SourceReader<R: io::Read> {
+    Plain(R),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+}
+
+impl<R: io::Read> SourceReader<R> {
+    /// Reader decompressing data of a compression with id from the header, with an external dictionary if the compression uses one.<br>
+    /// Exact readers don't read compressed data ahead, so the source is left right after the document
+    fn decompressing(
+        reader: R,
+        compression: u8,
+        exact: bool,
+        dictionary: Option<&[u8]>,
+    ) -> Result<Self, DeserializerInitError> {
+        match compression {
+            #[cfg(feature = "zstd")]
+            crate::compress::COMPRESSION_ZSTD | crate::COMPRESSION_ZSTD_DICTIONARY => {
+                let buffer = match exact {
+                    true => io::BufReader::with_capacity(1, reader),
+                    false => io::BufReader::new(reader),
+                };
+                let decoder = zstd::stream::read::Decoder::with_dictionary(buffer, dictionary.unwrap_or_default())?;
+                Ok(Self::Zstd(decoder.single_frame()))
+            }
+            #[cfg(feature = "lz4")]
+            crate::compress::COMPRESSION_LZ4 => Ok(Self::Lz4(lz4_flex::frame::FrameDecoder::new(reader))),
+            id => {
+                let _ = (reader, exact, dictionary);
+                Err(DeserializerInitError::UnsupportedCompression(id))
+            }
+        }
+    }
+
+    /// Read the end of compressed data, which isn't read together with the last decompressed bytes
+    fn finish(&mut self) -> Result<(), io::Error> {
+        if matches!(self, Self::Plain(_)) {
+            return Ok(());
+        }
+        let mut buf = [0u8; 64];
+        match self.as_read().read(&mut buf)? {
+            0 => Ok(()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Compressed data continues after the document")),
+        }
+    }
+
+    fn as_read(&mut self) -> &mut dyn io::Read {
+        match self {
+            Self::Plain(reader) => reader,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(reader) => reader,
+            #[cfg(feature = "lz4")]
+            Self::Lz4(reader) => reader,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.as_read().read(buf)?;
+        self.position += read as u64;
+        #[cfg(feature = "checksum")]
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.as_read().read_exact(buf)?;
+        #[cfg(feature = "checksum")]
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(buf);
+        }
+        self.position += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Deserializer string map size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StringMapStats {
+    /// Amount of strings
+    pub count: usize,
+
+    /// Total length of strings in bytes
+    pub bytes: usize,
+}
+
+/// Callback loading a byte array stored outside of the data by its reference
+pub type BlobLoader = dyn Fn(&str) -> io::Result<Vec<u8>> + Send + Sync;
+
 pub struct Deserializer<R: io::Read> {
-    pub(crate) reader: R,
+    pub(crate) reader: PositionReader<R>,
     pub(crate) string_map: BTreeMap<u32, Arc<str>>,
     tag_peek: Option<TypeTag>,
     level: usize,
+    string_hook: Option<Box<StringHook>>,
+    should_continue: Option<Box<ContinueCheck>>,
+    options: DeserializerOptions,
+    profile: Option<Box<Profile>>,
+
+    /// Last read string, names map keys in error paths
+    last_str: Option<Arc<str>>,
 
     #[allow(unused)]
     data_version: u8,
 }
 
+/// Deserializer options shared with nested deserializers, see `Deserializer::new_nested`
+#[derive(Clone, Default)]
+struct DeserializerOptions {
+    #[cfg(feature = "unicode")]
+    nfc_strings: bool,
+    float_policy: FloatPolicy,
+    duplicate_fields: DuplicateFieldPolicy,
+    blob_loader: Option<Arc<BlobLoader>>,
+    skip_reserved_tags: bool,
+    tolerant_variants: bool,
+    tolerant_units: bool,
+    compact_structs: bool,
+}
+
 impl<R: io::Read> Deserializer<R> {
     /// Construct a new Deserializer.<br>
-    /// Reader preferred to be buffered, deserialization does many small reads
-    pub fn new(mut reader: R) -> Result<Self, DeserializerInitError> {
+    /// Reader preferred to be buffered, deserialization does many small reads.<br>
+    /// Compressed documents are decompressed transparently, reading them may consume data past the document,
+    /// use [`Self::new_exact`] for readers with more data after it
+    pub fn new(reader: R) -> Result<Self, DeserializerInitError> {
+        Self::open(reader, false, |_| None)
+    }
+
+    /// Construct a new Deserializer that doesn't read past the end of the document, even if it's compressed,
+    /// once it's read along with [`Self::finish`].<br>
+    /// Compressed data is read from the reader in single bytes, so it should be buffered or in memory
+    pub fn new_exact(reader: R) -> Result<Self, DeserializerInitError> {
+        Self::open(reader, true, |_| None)
+    }
+
+    /// Construct a new Deserializer also reading documents compressed with one of external zstd `dictionaries`,
+    /// see `Serializer::new_compressed_with_dictionary`.<br>
+    /// Other documents are read the same as with [`Self::new`]
+    #[cfg(feature = "zstd")]
+    pub fn new_with_dictionaries(
+        reader: R,
+        dictionaries: &[crate::compress::ZstdDictionary],
+    ) -> Result<Self, DeserializerInitError> {
+        Self::open(reader, false, |id| dictionaries.iter().find(|d| d.id() == id).map(|d| d.data()))
+    }
+
+    fn open<'d>(
+        mut reader: R,
+        exact: bool,
+        dictionary: impl FnOnce(u32) -> Option<&'d [u8]>,
+    ) -> Result<Self, DeserializerInitError> {
         if !read_check_eq(&mut reader, MAGIC_HEADER)? {
             return Err(DeserializerInitError::InvalidHeader);
         }
@@ -149,33 +426,378 @@ impl<R: io::Read> Deserializer<R> {
         let mut ver = 0u8;
         reader.read_exact(slice::from_mut(&mut ver))?;
 
+        let has_flags = ver & crate::HEADER_FLAGS_BIT != 0;
+        let ver = ver & !crate::HEADER_FLAGS_BIT;
+
         if ver > FORMAT_VERSION {
             return Err(DeserializerInitError::UnsupportedVersion(ver));
         }
 
-        Ok(Self::new_bare(reader, ver))
+        let mut header_len = MAGIC_HEADER.len() + 1;
+        let mut flags = 0u8;
+        if has_flags {
+            reader.read_exact(slice::from_mut(&mut flags))?;
+            header_len += 1;
+        }
+
+        // Trailer of checksummed documents can't be read without the feature
+        #[cfg(not(feature = "checksum"))]
+        if flags & crate::HEADER_CHECKSUM_FLAG != 0 {
+            return Err(DeserializerInitError::UnsupportedChecksum);
+        }
+
+        let compression = flags & !crate::HEADER_CHECKSUM_FLAG;
+        #[cfg(feature = "zstd")]
+        let dictionary = match compression == crate::COMPRESSION_ZSTD_DICTIONARY {
+            true => {
+                let mut id = [0u8; 4];
+                reader.read_exact(&mut id)?;
+                header_len += id.len();
+                let id = u32::from_le_bytes(id);
+                Some(dictionary(id).ok_or(DeserializerInitError::UnknownDictionary(id))?)
+            }
+            false => None,
+        };
+        #[cfg(not(feature = "zstd"))]
+        let dictionary = {
+            let _ = dictionary;
+            None
+        };
+
+        let reader = match compression {
+            0 => SourceReader::Plain(reader),
+            compression => SourceReader::decompressing(reader, compression, exact, dictionary)?,
+        };
+
+        let mut this = Self::with_source(reader, ver);
+        this.reader.position = header_len as u64;
+        #[cfg(feature = "checksum")]
+        if flags & crate::HEADER_CHECKSUM_FLAG != 0 {
+            this.reader.checksum = Some(crc32fast::Hasher::new());
+        }
+        Ok(this)
     }
 
     pub(crate) fn new_bare(reader: R, data_version: u8) -> Self {
+        Self::with_source(SourceReader::Plain(reader), data_version)
+    }
+
+    fn with_source(reader: SourceReader<R>, data_version: u8) -> Self {
         Self {
-            reader,
+            reader: PositionReader {
+                reader,
+                position: 0,
+                #[cfg(feature = "checksum")]
+                checksum: None,
+            },
             string_map: Default::default(),
             tag_peek: None,
             level: 0,
+            string_hook: None,
+            should_continue: None,
+            options: Default::default(),
+            profile: None,
+            last_str: None,
             data_version,
         }
     }
 
+    /// Create a bare deserializer with the same options, for deserializing separately read values.<br>
+    /// Callbacks aren't shared, profile of the nested deserializer is merged back by [`Self::deserialize_nested`]
+    fn new_nested<N: io::Read>(&self, reader: N) -> Deserializer<N> {
+        let mut de = Deserializer::new_bare(reader, self.data_version);
+        de.options = self.options.clone();
+        de.profile = self.profile.as_ref().map(|_| Default::default());
+        de
+    }
+
+    /// Deserialize a value from separately read data with a nested deserializer
+    fn deserialize_nested<'de, S: serde::de::DeserializeSeed<'de>>(
+        &mut self,
+        data: &[u8],
+        seed: S,
+    ) -> Result<S::Value, DeserializeError> {
+        let mut de = self.new_nested(data);
+        let res = seed.deserialize(&mut de);
+        if let (Some(profile), Some(nested)) = (&mut self.profile, &de.profile) {
+            profile.merge(nested);
+        }
+        res
+    }
+
+    /// Set how read NaN and infinite float values are handled
+    pub fn with_float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.options.float_policy = policy;
+        self
+    }
+
+    /// Set how struct fields that appear more than once are handled.<br>
+    /// With policies other than `Error`, struct fields are read ahead before being passed to the visitor
+    pub fn with_duplicate_fields(mut self, policy: DuplicateFieldPolicy) -> Self {
+        self.options.duplicate_fields = policy;
+        self
+    }
+
+    /// Skip optional data of newer format versions, written with tags from `RESERVED_SKIPPABLE_TAGS`, instead of erroring
+    pub fn with_skip_reserved_tags(mut self, skip: bool) -> Self {
+        self.options.skip_reserved_tags = skip;
+        self
+    }
+
+    /// Allow reading enum variants whose data doesn't match the variant type, as happens when fields are added to or removed from a variant.<br>
+    /// Data of variants read as unit variants is skipped. Unit variants read as struct variants are read as having no fields,
+    /// so fields with `#[serde(default)]` get their defaults, and unit variants read as newtype variants are read from `()`, which fits `Option`
+    pub fn with_tolerant_variants(mut self, tolerant: bool) -> Self {
+        self.options.tolerant_variants = tolerant;
+        self
+    }
+
+    /// Allow reading unit structs, `()` and structs with fields from each other, as happens when a unit struct gets fields or loses them.<br>
+    /// Data of structs read as units is skipped, units read as structs are read as having no fields
+    pub fn with_tolerant_units(mut self, tolerant: bool) -> Self {
+        self.options.tolerant_units = tolerant;
+        self
+    }
+
+    /// Read struct variants written by a Serializer with compact structs, whose fields are written positionally.<br>
+    /// Compact data isn't marked, so without this tuple variants can't be read as struct variants
+    pub fn with_compact_structs(mut self, compact: bool) -> Self {
+        self.options.compact_structs = compact;
+        self
+    }
+
+    /// Whether the next value is a struct to be read as a unit, skipping it if so
+    fn skip_struct_as_unit(&mut self) -> Result<bool, DeserializeError> {
+        if !self.options.tolerant_units {
+            return Ok(false);
+        }
+        match self.peek_tag()? {
+            TypeTag::Struct(StructType::Struct | StructType::Tuple) => {
+                self.skip_value()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Whether the next value is a unit to be read as a struct, consuming it if so
+    fn read_unit_as_struct(&mut self) -> Result<bool, DeserializeError> {
+        if !self.options.tolerant_units {
+            return Ok(false);
+        }
+        match self.peek_tag()? {
+            TypeTag::Unit | TypeTag::Struct(StructType::Unit) => {
+                self.read_tag()?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Set a callback loading byte arrays written by a Serializer with a blob store
+    pub fn with_blob_loader(
+        mut self,
+        loader: impl Fn(&str) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.options.blob_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Set a callback which receives every new string read from the data.<br>
+    /// Interned strings are passed once, when they're first read.
+    /// Returned string replaces the read one, allowing to deduplicate strings into external caches
+    pub fn with_string_hook(
+        mut self,
+        hook: impl FnMut(Arc<str>) -> Arc<str> + Send + 'static,
+    ) -> Self {
+        self.string_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Normalize read strings to Unicode NFC before passing them to the string hook,
+    /// so strings written by platforms using different normalizations, such as map keys, compare equal
+    #[cfg(feature = "unicode")]
+    pub fn with_nfc_strings(mut self, normalize: bool) -> Self {
+        self.options.nfc_strings = normalize;
+        self
+    }
+
+    /// Read the checksum trailer and compare it to the checksum of read data, for documents written with `Serializer::new_checksummed`.<br>
+    /// Must be called after reading the whole document. Does nothing for documents without a checksum or if it was already verified
+    #[cfg(feature = "checksum")]
+    pub fn verify_checksum(&mut self) -> Result<(), DeserializeError> {
+        let Some(checksum) = self.reader.checksum.take() else {
+            return Ok(());
+        };
+
+        let mut trailer = [0u8; crate::checksum::TRAILER_LEN];
+        self.reader.read_exact(&mut trailer)?;
+        crate::checksum::check(checksum.finalize(), trailer)
+    }
+
+    /// Finish reading a document after reading its value, verifying its checksum and reading the end of compressed data.<br>
+    /// Reader of [`Self::new_exact`] is left right after the document
+    pub fn finish(&mut self) -> Result<(), DeserializeError> {
+        #[cfg(feature = "checksum")]
+        self.verify_checksum()?;
+        self.reader.reader.finish()?;
+        Ok(())
+    }
+
+    /// Set a callback checked before reading every value, aborting reading with `DeserializeError::TimedOut` once it returns false.<br>
+    /// Only checked between reads, so a reader blocking on a slow source should also have its own read timeout
+    pub fn with_should_continue(mut self, check: impl FnMut() -> bool + Send + 'static) -> Self {
+        self.should_continue = Some(Box::new(check));
+        self
+    }
+
+    /// Abort reading with `DeserializeError::TimedOut` once the deadline passes, see [`Self::with_should_continue`].<br>
+    /// Not available on wasm32-unknown-unknown, where `Instant::now` panics
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn with_deadline(self, deadline: std::time::Instant) -> Self {
+        self.with_should_continue(move || std::time::Instant::now() < deadline)
+    }
+
+    /// Enable recording per-type deserialization time and size
+    pub fn with_profiler(mut self) -> Self {
+        self.profile = Some(Default::default());
+        self
+    }
+
+    /// Statistics recorded so far, if profiling is enabled
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    /// Amount and total length of strings kept in the string map
+    pub fn string_map_stats(&self) -> StringMapStats {
+        StringMapStats {
+            count: self.string_map.len(),
+            bytes: self.string_map.values().map(|s| s.len()).sum(),
+        }
+    }
+
+    /// Forget all strings read so far, freeing memory used by them.<br>
+    /// Only safe to use before data that doesn't reference earlier strings, otherwise reading it fails with `InvalidStringId`
+    pub fn clear_string_map(&mut self) {
+        self.string_map = Default::default();
+    }
+
+    fn profiled<T>(
+        &mut self,
+        name: &'static str,
+        f: impl FnOnce(&mut Self) -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        if self.profile.is_none() {
+            return f(self);
+        }
+
+        let start = Timer::start();
+        let start_pos = self.position();
+        let res = f(self);
+        let bytes = self.position() - start_pos;
+
+        if let Some(profile) = &mut self.profile {
+            profile.record(name, start.elapsed(), bytes);
+        }
+
+        res
+    }
+
+    /// Amount of bytes consumed by this deserializer, including the header
+    pub fn position(&self) -> u64 {
+        match self.tag_peek {
+            Some(_) => self.reader.position - 1,
+            None => self.reader.position,
+        }
+    }
+
+    /// Whether read strings are changed by apply_string_hook
+    fn has_string_hook(&self) -> bool {
+        #[cfg(feature = "unicode")]
+        if self.options.nfc_strings {
+            return true;
+        }
+        self.string_hook.is_some()
+    }
+
+    fn apply_string_hook(&mut self, str: Arc<str>) -> Arc<str> {
+        #[cfg(feature = "unicode")]
+        let str = match self.options.nfc_strings && !unicode_normalization::is_nfc(&str) {
+            true => {
+                let normalized: String = unicode_normalization::UnicodeNormalization::nfc(&*str).collect();
+                normalized.into()
+            }
+            false => str,
+        };
+
+        match &mut self.string_hook {
+            Some(hook) => hook(str),
+            None => str,
+        }
+    }
+
     pub(crate) fn read_tag(&mut self) -> Result<TypeTag, ReadTagError> {
         if let Some(tag) = self.tag_peek.take() {
             return Ok(tag);
         }
 
-        let mut byte = 0u8;
-        self.reader.read_exact(slice::from_mut(&mut byte))?;
-        FlatTypeTag::try_from(byte)
-            .map(Into::into)
-            .map_err(ReadTagError::InvalidTag)
+        self.read_new_tag()
+    }
+
+    /// Read a tag from the reader, skipping reserved tags with their data if enabled
+    fn read_new_tag(&mut self) -> Result<TypeTag, ReadTagError> {
+        if let Some(check) = &mut self.should_continue {
+            if !check() {
+                return Err(ReadTagError::TimedOut);
+            }
+        }
+
+        loop {
+            let mut byte = 0u8;
+            self.reader.read_exact(slice::from_mut(&mut byte))?;
+            if let Some(tag) = self.parse_tag_byte(byte)? {
+                return Ok(tag);
+            }
+        }
+    }
+
+    /// Parse a read tag byte, returns None if it was a skipped reserved tag
+    fn parse_tag_byte(&mut self, byte: u8) -> Result<Option<TypeTag>, ReadTagError> {
+        match FlatTypeTag::try_from(byte) {
+            Ok(tag) => Ok(Some(tag.into())),
+            Err(byte) if RESERVED_SKIPPABLE_TAGS.contains(&byte) => {
+                if !self.options.skip_reserved_tags {
+                    return Err(ReadTagError::ReservedTag(byte));
+                }
+                let len = varint::read_length(&mut self.reader)? as u64;
+                let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+                if skipped != len {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(None)
+            }
+            Err(byte) => Err(ReadTagError::InvalidTag(byte)),
+        }
+    }
+
+    /// Check whether the reader has ended at a value boundary.<br>
+    /// EOF in the middle of a value is still an error when reading it
+    pub fn is_at_end(&mut self) -> Result<bool, DeserializeError> {
+        while self.tag_peek.is_none() {
+            let mut byte = 0u8;
+            let read = loop {
+                match self.reader.read(slice::from_mut(&mut byte)) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    res => break res?,
+                }
+            };
+            if read == 0 {
+                return Ok(true);
+            }
+            self.tag_peek = self.parse_tag_byte(byte)?;
+        }
+        Ok(false)
     }
 
     pub(crate) fn peek_tag(&mut self) -> Result<TypeTag, ReadTagError> {
@@ -183,11 +805,7 @@ impl<R: io::Read> Deserializer<R> {
             return Ok(tag);
         }
 
-        let mut byte = 0u8;
-        self.reader.read_exact(slice::from_mut(&mut byte))?;
-        let tag = FlatTypeTag::try_from(byte)
-            .map(Into::into)
-            .map_err(ReadTagError::InvalidTag)?;
+        let tag = self.read_new_tag()?;
         self.tag_peek = Some(tag);
         Ok(tag)
     }
@@ -196,6 +814,56 @@ impl<R: io::Read> Deserializer<R> {
         self.tag_peek.take()
     }
 
+    /// Read key type of a primitive key map, which must be an integer or char tag, or a tuple of those
+    pub(crate) fn read_key_type(&mut self) -> Result<KeyType, DeserializeError> {
+        match self.read_primitive_key_tag(true)? {
+            TypeTag::Tuple => {
+                let len = varint::read_length(&mut self.reader)?;
+                let tags = (0..len)
+                    .map(|_| self.read_primitive_key_tag(false))
+                    .collect::<Result<_, _>>()?;
+                Ok(KeyType::Tuple(tags))
+            }
+            tag => Ok(KeyType::Primitive(tag)),
+        }
+    }
+
+    fn read_primitive_key_tag(&mut self, allow_tuple: bool) -> Result<TypeTag, DeserializeError> {
+        let mut byte = 0u8;
+        self.reader.read_exact(slice::from_mut(&mut byte))?;
+        match FlatTypeTag::try_from(byte).map(TypeTag::from) {
+            Ok(tag @ (TypeTag::Integer { .. } | TypeTag::Char { .. })) => Ok(tag),
+            Ok(TypeTag::Tuple) if allow_tuple => Ok(TypeTag::Tuple),
+            _ => Err(DeserializeError::InvalidTag(byte)),
+        }
+    }
+
+    /// Deserialize an untagged key of a primitive key map.<br>
+    /// Tuple keys are read into a regular tuple first, so they're deserialized the same way as tuples of other maps
+    fn deserialize_primitive_key<'de, S: serde::de::DeserializeSeed<'de>>(
+        &mut self,
+        key: &KeyType,
+        seed: S,
+    ) -> Result<S::Value, DeserializeError> {
+        match key {
+            KeyType::Primitive(tag) => {
+                self.tag_peek = Some(*tag);
+                seed.deserialize(self)
+            }
+            KeyType::Tuple(tags) => {
+                let mut buf = vec![];
+                let mut se = crate::ser::Serializer::new_bare(&mut buf, 0);
+                se.write_tag(TypeTag::Tuple)?;
+                varint::write_unsigned_varint(&mut se.writer, tags.len())?;
+                for tag in tags.iter() {
+                    se.write_tag(*tag)?;
+                    crate::raw::RawValue::copy_tag_data(self, &mut se, *tag)?;
+                }
+                self.deserialize_nested(&buf, seed)
+            }
+        }
+    }
+
     pub(crate) fn read_str_by_index(&mut self) -> Result<Arc<str>, ReadStrError> {
         let index = varint::read_unsigned_varint(&mut self.reader)?;
         let str = self
@@ -205,24 +873,36 @@ impl<R: io::Read> Deserializer<R> {
         Ok(str.clone())
     }
 
+    /// Read `len` bytes of data.<br>
+    /// Length is untrusted, so allocation grows as data arrives instead of being done upfront
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, io::Error> {
+        let mut data = Vec::with_capacity(len.min(MAX_PREALLOCATION));
+        (&mut self.reader).take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(data)
+    }
+
     pub(crate) fn read_str_new(&mut self) -> Result<Arc<str>, ReadStrError> {
         let index = varint::read_unsigned_varint(&mut self.reader)?;
-        let len = varint::read_unsigned_varint(&mut self.reader)?;
-        let mut data = vec![0u8; len];
-        self.reader.read_exact(&mut data)?;
+        let len = varint::read_length(&mut self.reader)?;
+        let data = self.read_bytes(len)?;
         let string = String::from_utf8(data).map_err(|_| ReadStrError::InvalidUTF8String)?;
+        let string = self.apply_string_hook(string.into());
 
-        let boxed = self.string_map.entry(index).or_default();
-        *boxed = string.into();
+        self.string_map.insert(index, string.clone());
 
-        Ok(boxed.clone())
+        Ok(string)
     }
 
     pub(crate) fn read_str(&mut self, ty: StrNewIndex) -> Result<Arc<str>, ReadStrError> {
-        match ty {
+        let str = match ty {
             StrNewIndex::New => self.read_str_new(),
             StrNewIndex::Index => self.read_str_by_index(),
-        }
+        }?;
+        self.last_str = Some(str.clone());
+        Ok(str)
     }
 
     fn visit_enum<'de, V: serde::de::Visitor<'de>>(
@@ -242,26 +922,326 @@ impl<R: io::Read> Deserializer<R> {
         visitor.visit_enum(access)
     }
 
+    /// Read all struct fields ahead and visit them, dropping duplicates according to the duplicate field policy
+    fn visit_buffered_fields<'de, V: serde::de::Visitor<'de>>(
+        &mut self,
+        visitor: V,
+        len: usize,
+    ) -> Result<V::Value, DeserializeError> {
+        let mut fields = vec![];
+        for _ in 0..len {
+            let key = StringDeserializer {
+                de: self,
+                source: StrSource::ReadTag,
+            }
+            .read_str()?;
+            let value = crate::raw::RawValue::deserialize_raw(self)?;
+            fields.push((key, value));
+        }
+
+        let mut seen = HashSet::new();
+        match self.options.duplicate_fields {
+            DuplicateFieldPolicy::Error => {}
+            DuplicateFieldPolicy::FirstWins => fields.retain(|(key, _)| seen.insert(key.clone())),
+            DuplicateFieldPolicy::LastWins => {
+                fields.reverse();
+                fields.retain(|(key, _)| seen.insert(key.clone()));
+                fields.reverse();
+            }
+        }
+
+        visitor.visit_map(BufferedFieldsAccess {
+            de: self,
+            fields: fields.into_iter(),
+            value: None,
+        })
+    }
+
     fn visit_map<'de, V: serde::de::Visitor<'de>>(
         &mut self,
         visitor: V,
         len: Option<usize>,
         string_keys: bool,
+        key_type: Option<KeyType>,
     ) -> Result<V::Value, DeserializeError> {
         self.level += 1;
         let map = MapAccess {
             level: self.level,
             de: self,
             string_keys,
+            key_type,
             next_value: false,
             remaining: len,
             done: false,
+            key: None,
         };
 
         visitor.visit_map(map)
     }
 }
 
+impl<R: io::Read> Deserializer<R> {
+    /// Deserialize the next value, same as `T::deserialize(&mut deserializer)`
+    pub fn read_value<T: DeserializeOwned>(&mut self) -> Result<T, DeserializeError> {
+        T::deserialize(self)
+    }
+
+    /// Skip the next value without deserializing it.<br>
+    /// New strings in skipped data are still remembered, as later data can reference them
+    pub fn skip_value(&mut self) -> Result<(), DeserializeError> {
+        let mut se = crate::ser::Serializer::new_bare(io::sink(), 0);
+        crate::raw::RawValue::copy_value(self, &mut se)
+    }
+
+    /// Read a byte array directly into a buffer of its exact length, without allocating.<br>
+    /// For large arrays with length known ahead, such as one written in an earlier field
+    pub fn read_bytes_into(&mut self, buf: &mut [u8]) -> Result<(), DeserializeError> {
+        let tag = self.read_tag()?;
+        if !matches!(tag, TypeTag::Bytes) {
+            return Err(DeserializeError::Expected("bytes", tag.into()));
+        }
+
+        let len = varint::read_length(&mut self.reader)?;
+        if len != buf.len() {
+            return Err(DeserializeError::LengthMismatch {
+                expected: buf.len(),
+                read: len,
+            });
+        }
+        self.reader.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Start reading a map or struct entry by entry, allowing to skip unneeded values
+    pub fn read_map(&mut self) -> Result<MapReader<'_, R>, DeserializeError> {
+        let tag = self.read_tag()?;
+        let (remaining, key_type) = match tag {
+            TypeTag::Map { has_length } => {
+                let len = has_length.then(|| varint::read_length(&mut self.reader)).transpose()?;
+                (len, None)
+            }
+            TypeTag::Struct(StructType::Struct) => (Some(varint::read_length(&mut self.reader)?), None),
+            TypeTag::PrimitiveKeyMap => {
+                let len = varint::read_length(&mut self.reader)?;
+                (Some(len), Some(self.read_key_type()?))
+            }
+            tag => return Err(DeserializeError::Expected("map", tag.into())),
+        };
+
+        Ok(MapReader {
+            de: self,
+            remaining,
+            key_type,
+            value_next: false,
+        })
+    }
+}
+
+/// Iterator deserializing values placed one after another until the reader ends, as written by `StreamWriter` or [`crate::write_all_docs`].<br>
+/// Strings read in earlier values stay known to later ones, until the writer clears its string map. Stops after the first error
+pub struct StreamReader<T, R: io::Read> {
+    de: Deserializer<R>,
+    failed: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, R: io::Read> StreamReader<T, R> {
+    pub fn new(de: Deserializer<R>) -> Self {
+        Self {
+            de,
+            failed: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Forget all strings read so far, see [`Deserializer::clear_string_map`]
+    pub fn clear_string_map(&mut self) {
+        self.de.clear_string_map();
+    }
+
+    pub fn into_inner(self) -> Deserializer<R> {
+        self.de
+    }
+}
+
+impl<T: DeserializeOwned, R: io::Read> Iterator for StreamReader<T, R> {
+    type Item = Result<T, DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let res = match self.de.is_at_end() {
+            Ok(true) => return None,
+            Ok(false) => self.de.read_value(),
+            Err(e) => Err(e),
+        };
+        self.failed = res.is_err();
+        Some(res)
+    }
+}
+
+/// Reader of map entries, created by `Deserializer::read_map`
+pub struct MapReader<'a, R: io::Read> {
+    de: &'a mut Deserializer<R>,
+    remaining: Option<usize>,
+
+    /// Type of untagged keys, if this is a primitive key map
+    key_type: Option<KeyType>,
+    value_next: bool,
+}
+
+impl<R: io::Read> MapReader<'_, R> {
+    /// Check if there's another entry, consuming the End tag of maps without length
+    fn has_next(&mut self) -> Result<bool, DeserializeError> {
+        if self.value_next {
+            return Err(DeserializeError::TriedKeyGotValue);
+        }
+
+        match &mut self.remaining {
+            Some(0) => Ok(false),
+            Some(rem) => {
+                *rem -= 1;
+                Ok(true)
+            }
+            None => {
+                if matches!(self.de.peek_tag()?, TypeTag::End) {
+                    self.de.peek_tag_consume();
+                    self.remaining = Some(0);
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Amount of entries left to read, None if the map has no length and is not ended yet
+    pub fn remaining(&self) -> Option<usize> {
+        self.remaining
+    }
+
+    /// Lower and upper bounds of the amount of entries left to read, like `Iterator::size_hint`
+    pub fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining {
+            Some(rem) => (rem, Some(rem)),
+            None => (0, None),
+        }
+    }
+
+    /// Read next key, None if the map has ended
+    pub fn read_key<K: DeserializeOwned>(&mut self) -> Result<Option<K>, DeserializeError> {
+        if !self.has_next()? {
+            return Ok(None);
+        }
+        let key = match &self.key_type {
+            Some(key_type) => self.de.deserialize_primitive_key(key_type, PhantomData::<K>)?,
+            None => K::deserialize(&mut *self.de)?,
+        };
+        self.value_next = true;
+        Ok(Some(key))
+    }
+
+    /// Read value of the last read key
+    pub fn read_value<V: DeserializeOwned>(&mut self) -> Result<V, DeserializeError> {
+        if !self.value_next {
+            return Err(DeserializeError::TriedValedGotKey);
+        }
+        self.value_next = false;
+        V::deserialize(&mut *self.de)
+    }
+
+    /// Skip value of the last read key
+    pub fn skip_value(&mut self) -> Result<(), DeserializeError> {
+        if !self.value_next {
+            return Err(DeserializeError::TriedValedGotKey);
+        }
+        self.value_next = false;
+        self.de.skip_value()
+    }
+
+    /// Read next key and value, None if the map has ended
+    pub fn read_entry<K: DeserializeOwned, V: DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<(K, V)>, DeserializeError> {
+        let Some(key) = self.read_key()? else {
+            return Ok(None);
+        };
+        let value = self.read_value()?;
+        Ok(Some((key, value)))
+    }
+
+    /// Skip next key and value, returns false if the map has ended
+    pub fn skip_entry(&mut self) -> Result<bool, DeserializeError> {
+        if !self.has_next()? {
+            return Ok(false);
+        }
+        match &self.key_type {
+            Some(KeyType::Primitive(tag)) => {
+                self.de.tag_peek = Some(*tag);
+                self.de.skip_value()?;
+            }
+            Some(KeyType::Tuple(tags)) => {
+                let mut se = crate::ser::Serializer::new_bare(io::sink(), 0);
+                for tag in tags.iter() {
+                    crate::raw::RawValue::copy_tag_data(self.de, &mut se, *tag)?;
+                }
+            }
+            None => self.de.skip_value()?,
+        }
+        self.de.skip_value()?;
+        Ok(true)
+    }
+
+    /// Capture the unread rest of the map as a RawValue holding a map, such as for logging entries that couldn't be read.<br>
+    /// Keys of primitive key maps are captured with their tags. If a key was read without its value, the value is captured under a unit key
+    pub fn into_raw_rest(mut self) -> Result<crate::RawValue, DeserializeError> {
+        use crate::RawValue;
+
+        let mut buf = vec![];
+        let mut se = crate::Serializer::new_bare(&mut buf, 256);
+
+        let remaining = self.remaining.map(|rem| rem + self.value_next as usize);
+        se.write_tag(TypeTag::Map {
+            has_length: remaining.is_some(),
+        })?;
+        if let Some(len) = remaining {
+            varint::write_unsigned_varint(&mut se.writer, len)?;
+        }
+
+        if self.value_next {
+            self.value_next = false;
+            se.write_tag(TypeTag::Unit)?;
+            RawValue::copy_value(self.de, &mut se)?;
+        }
+
+        while self.has_next()? {
+            match &self.key_type {
+                Some(KeyType::Primitive(tag)) => {
+                    se.write_tag(*tag)?;
+                    RawValue::copy_tag_data(self.de, &mut se, *tag)?;
+                }
+                Some(KeyType::Tuple(tags)) => {
+                    se.write_tag(TypeTag::Tuple)?;
+                    varint::write_unsigned_varint(&mut se.writer, tags.len())?;
+                    for tag in tags.iter() {
+                        se.write_tag(*tag)?;
+                        RawValue::copy_tag_data(self.de, &mut se, *tag)?;
+                    }
+                }
+                None => RawValue::copy_value(self.de, &mut se)?,
+            }
+            RawValue::copy_value(self.de, &mut se)?;
+        }
+
+        if remaining.is_none() {
+            se.write_tag(TypeTag::End)?;
+        }
+        drop(se);
+        Ok(RawValue::from_bytes(buf.into_boxed_slice()))
+    }
+}
+
 impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
     type Error = DeserializeError;
 
@@ -326,6 +1306,7 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
                     visitor.visit_u64(u64::from_le_bytes(buf))
                 }
             }
+            #[cfg(feature = "int128")]
             TypeTag::Integer {
                 width: IntWidth::W128,
                 signed,
@@ -359,6 +1340,7 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
                 signed: false,
                 varint: true,
             } => visitor.visit_u64(varint::read_unsigned_varint(&mut self.reader)?),
+            #[cfg(feature = "int128")]
             TypeTag::Integer {
                 width: IntWidth::W128,
                 signed: false,
@@ -384,11 +1366,17 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
                 signed: true,
                 varint: true,
             } => visitor.visit_i64(varint::read_signed_varint(&mut self.reader)?),
+            #[cfg(feature = "int128")]
             TypeTag::Integer {
                 width: IntWidth::W128,
                 signed: true,
                 varint: true,
             } => visitor.visit_i128(varint::read_signed_varint(&mut self.reader)?),
+            #[cfg(not(feature = "int128"))]
+            TypeTag::Integer {
+                width: IntWidth::W128,
+                ..
+            } => Err(DeserializeError::Int128Disabled),
             TypeTag::Char { varint: false } => {
                 let mut buf = [0u8; 4];
                 self.reader.read_exact(&mut buf)?;
@@ -404,39 +1392,63 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
             TypeTag::Float(FloatWidth::F32) => {
                 let mut buf = [0u8; 4];
                 self.reader.read_exact(&mut buf)?;
-                visitor.visit_f32(f32::from_le_bytes(buf))
+                let float = self
+                    .options
+                    .float_policy
+                    .apply_f32(f32::from_le_bytes(buf))
+                    .ok_or_else(|| DeserializeError::NonFiniteFloat(String::new()))?;
+                visitor.visit_f32(float)
             },
             TypeTag::Float(FloatWidth::F64) => {
                 let mut buf = [0u8; 8];
                 self.reader.read_exact(&mut buf)?;
-                visitor.visit_f64(f64::from_le_bytes(buf))
+                let float = self
+                    .options
+                    .float_policy
+                    .apply_f64(f64::from_le_bytes(buf))
+                    .ok_or_else(|| DeserializeError::NonFiniteFloat(String::new()))?;
+                visitor.visit_f64(float)
             },
             TypeTag::Str(sni) => {
                 visitor.visit_str(&self.read_str(sni)?)
             },
             TypeTag::StrDirect => {
-                let len = varint::read_unsigned_varint(&mut self.reader)?;
-                let mut data = vec![0u8; len];
-                self.reader.read_exact(&mut data)?;
+                let len = varint::read_length(&mut self.reader)?;
+                let data = self.read_bytes(len)?;
                 let string =
                     String::from_utf8(data).map_err(|_| DeserializeError::InvalidUTF8String)?;
-                visitor.visit_string(string)
+                if self.has_string_hook() {
+                    let string = self.apply_string_hook(string.into());
+                    visitor.visit_str(&string)
+                } else {
+                    visitor.visit_string(string)
+                }
             },
             TypeTag::EmptyStr => visitor.visit_str(""),
             TypeTag::Bytes => {
-                let len = varint::read_unsigned_varint(&mut self.reader)?;
-                let mut data = vec![0u8; len];
-                self.reader.read_exact(&mut data)?;
+                let len = varint::read_length(&mut self.reader)?;
+                let data = self.read_bytes(len)?;
                 visitor.visit_byte_buf(data)
             },
+            TypeTag::ExternalBytes => {
+                let len = varint::read_length(&mut self.reader)?;
+                let data = self.read_bytes(len)?;
+                let reference =
+                    String::from_utf8(data).map_err(|_| DeserializeError::InvalidUTF8String)?;
+                let loader = self.options.blob_loader.as_ref().ok_or(DeserializeError::NoBlobLoader)?;
+                visitor.visit_byte_buf(loader(&reference)?)
+            },
             TypeTag::Option(OptionTag::None) => visitor.visit_none(),
             TypeTag::Option(OptionTag::Some) => visitor.visit_some(self),
             TypeTag::Struct(StructType::Unit) => visitor.visit_unit(),
             TypeTag::Struct(StructType::Newtype) => visitor.visit_newtype_struct(self),
 
             TypeTag::Struct(StructType::Struct) => {
-                let len = varint::read_unsigned_varint(&mut self.reader)?;
-                self.visit_map(visitor, Some(len), true)
+                let len = varint::read_length(&mut self.reader)?;
+                match self.options.duplicate_fields {
+                    DuplicateFieldPolicy::Error => self.visit_map(visitor, Some(len), true, None),
+                    _ => self.visit_buffered_fields(visitor, len),
+                }
             },
 
             TypeTag::EnumVariant { ty, str } => self.visit_enum(visitor, ty, str),
@@ -447,26 +1459,33 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
                     level: self.level,
                     de: self,
                     done: false,
+                    index: 0,
                 };
                 visitor.visit_seq(seq)
             },
 
             TypeTag::Seq { has_length: true } | TypeTag::Tuple | TypeTag::Struct(StructType::Tuple) => {
-                let len = varint::read_unsigned_varint(&mut self.reader)?;
+                let len = varint::read_length(&mut self.reader)?;
                 self.level += 1;
                 let seq = SeqAccess {
                     remaining: Some(len),
                     level: self.level,
                     de: self,
                     done: false,
+                    index: 0,
                 };
                 visitor.visit_seq(seq)
             }
 
             TypeTag::Map { has_length } => {
-                let len = has_length.then(|| varint::read_unsigned_varint(&mut self.reader)).transpose()?;
-                self.visit_map(visitor, len, false)
+                let len = has_length.then(|| varint::read_length(&mut self.reader)).transpose()?;
+                self.visit_map(visitor, len, false, None)
             },
+            TypeTag::PrimitiveKeyMap => {
+                let len = varint::read_length(&mut self.reader)?;
+                let key_type = self.read_key_type()?;
+                self.visit_map(visitor, Some(len), false, Some(key_type))
+            }
             TypeTag::End => Err(DeserializeError::ReadEnd),
         }
     }
@@ -506,6 +1525,7 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
         self.deserialize_any(visitor)
     }
 
+    #[cfg(feature = "int128")]
     fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
@@ -541,6 +1561,7 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
         self.deserialize_any(visitor)
     }
 
+    #[cfg(feature = "int128")]
     fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
@@ -608,18 +1629,26 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
     where
         V: serde::de::Visitor<'de>,
     {
+        if self.skip_struct_as_unit()? {
+            return visitor.visit_unit();
+        }
         self.deserialize_any(visitor)
     }
 
     fn deserialize_unit_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.profiled(name, |de| {
+            if de.skip_struct_as_unit()? {
+                return visitor.visit_unit();
+            }
+            de.deserialize_any(visitor)
+        })
     }
 
     fn deserialize_newtype_struct<V>(
@@ -634,7 +1663,7 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
             let buf = crate::raw::RawValue::deserialize_raw(self)?;
             return visitor.visit_bytes(&buf);
         }
-        self.deserialize_any(visitor)
+        self.profiled(name, |de| de.deserialize_any(visitor))
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -653,14 +1682,14 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
 
     fn deserialize_tuple_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.profiled(name, |de| de.deserialize_any(visitor))
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -672,26 +1701,32 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
 
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.profiled(name, |de| {
+            if de.read_unit_as_struct()? {
+                let fields = std::iter::empty::<(&str, ())>();
+                return visitor.visit_map(serde::de::value::MapDeserializer::new(fields));
+            }
+            de.deserialize_any(visitor)
+        })
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.profiled(name, |de| de.deserialize_any(visitor))
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -701,11 +1736,13 @@ impl<'de, R: io::Read> serde::Deserializer<'de> for &mut Deserializer<R> {
         self.deserialize_any(visitor)
     }
 
+    /// Ignored values, such as unknown struct fields, are skipped without being deserialized
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.skip_value()?;
+        visitor.visit_unit()
     }
 }
 
@@ -714,6 +1751,7 @@ struct SeqAccess<'a, R: io::Read> {
     de: &'a mut Deserializer<R>,
     done: bool,
     level: usize,
+    index: usize,
 }
 
 impl<'de, R: io::Read> serde::de::SeqAccess<'de> for SeqAccess<'_, R> {
@@ -750,7 +1788,10 @@ impl<'de, R: io::Read> serde::de::SeqAccess<'de> for SeqAccess<'_, R> {
             }
         }
 
-        let ret = seed.deserialize(&mut *self.de)?;
+        let ret = seed
+            .deserialize(&mut *self.de)
+            .map_err(|e| e.in_index(self.index))?;
+        self.index += 1;
 
         match &mut self.remaining {
             Some(rem) => {
@@ -774,7 +1815,7 @@ impl<'de, R: io::Read> serde::de::SeqAccess<'de> for SeqAccess<'_, R> {
     }
 
     fn size_hint(&self) -> Option<usize> {
-        self.remaining
+        self.remaining.map(|rem| rem.min(MAX_PREALLOCATION))
     }
 }
 
@@ -797,7 +1838,7 @@ impl<'de, 'a, R: io::Read> serde::de::EnumAccess<'de> for EnumAccess<'a, R> {
     {
         let ident = seed.deserialize(StringDeserializer {
             de: self.de,
-            str_ty: Some(self.str_ty),
+            source: StrSource::Tag(self.str_ty),
         })?;
 
         let access = VariantAccess {
@@ -828,12 +1869,39 @@ impl<R: io::Read> VariantAccess<'_, R> {
             Ok(())
         }
     }
+
+    /// Whether variant of type `ty` is read from a unit variant, which is only allowed with tolerant variants
+    fn is_unit_fallback(&self, ty: StructType) -> Result<bool, DeserializeError> {
+        if self.ty == StructType::Unit && self.de.options.tolerant_variants {
+            return Ok(true);
+        }
+        self.assert_type(ty)?;
+        Ok(false)
+    }
+
+    /// Skip data of a variant read as a unit variant
+    fn skip_data(self) -> Result<(), DeserializeError> {
+        let values = match self.ty {
+            StructType::Unit => 0,
+            StructType::Newtype => 1,
+            StructType::Tuple => varint::read_length(&mut self.de.reader)?,
+            StructType::Struct => varint::read_length(&mut self.de.reader)?.saturating_mul(2),
+        };
+        for _ in 0..values {
+            self.de.skip_value()?;
+        }
+        self.de.level -= 1;
+        Ok(())
+    }
 }
 
 impl<'de, R: io::Read> serde::de::VariantAccess<'de> for VariantAccess<'_, R> {
     type Error = DeserializeError;
 
     fn unit_variant(self) -> Result<(), Self::Error> {
+        if self.de.options.tolerant_variants {
+            return self.skip_data();
+        }
         self.assert_type(StructType::Unit)?;
         self.de.level -= 1;
         Ok(())
@@ -843,7 +1911,10 @@ impl<'de, R: io::Read> serde::de::VariantAccess<'de> for VariantAccess<'_, R> {
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        self.assert_type(StructType::Newtype)?;
+        if self.is_unit_fallback(StructType::Newtype)? {
+            self.de.level -= 1;
+            return seed.deserialize(serde::de::value::UnitDeserializer::new());
+        }
         let val = seed.deserialize(&mut *self.de);
         self.de.level -= 1;
         val
@@ -854,56 +1925,85 @@ impl<'de, R: io::Read> serde::de::VariantAccess<'de> for VariantAccess<'_, R> {
         V: serde::de::Visitor<'de>,
     {
         self.assert_type(StructType::Tuple)?;
-        let len = varint::read_unsigned_varint(&mut self.de.reader)?;
+        let len = varint::read_length(&mut self.de.reader)?;
         let seq = SeqAccess {
             remaining: Some(len),
             level: self.level,
             de: self.de,
             done: false,
+            index: 0,
         };
         visitor.visit_seq(seq)
     }
 
     fn struct_variant<V>(
         self,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        let len = varint::read_unsigned_varint(&mut self.de.reader)?;
+        // Compact structs are written positionally
+        if self.ty == StructType::Tuple && self.de.options.compact_structs {
+            return self.tuple_variant(fields.len(), visitor);
+        }
+
+        if self.is_unit_fallback(StructType::Struct)? {
+            self.de.level -= 1;
+            let fields = std::iter::empty::<(&str, ())>();
+            return visitor.visit_map(serde::de::value::MapDeserializer::new(fields));
+        }
+
+        let len = varint::read_length(&mut self.de.reader)?;
+        if self.de.options.duplicate_fields != DuplicateFieldPolicy::Error {
+            self.de.level -= 1;
+            return self.de.visit_buffered_fields(visitor, len);
+        }
+
         let map = MapAccess {
             de: self.de,
             level: self.level,
             string_keys: true,
+            key_type: None,
             next_value: false,
             remaining: Some(len),
             done: false,
+            key: None,
         };
 
         visitor.visit_map(map)
     }
 }
 
+enum StrSource {
+    /// Read string data of an already read tag
+    Tag(StrNewIndex),
+
+    /// Read a string tag and its data
+    ReadTag,
+
+    /// String was already read
+    Read(Arc<str>),
+}
+
 struct StringDeserializer<'a, R: io::Read> {
     de: &'a mut Deserializer<R>,
-
-    /// Deserialize a specific string on Some, or read a string tag and operate on that on None
-    str_ty: Option<StrNewIndex>,
+    source: StrSource,
 }
 
 impl<R: io::Read> StringDeserializer<'_, R> {
     fn read_str(self) -> Result<Arc<str>, DeserializeError> {
-        match self.str_ty {
-            Some(s) => self.de.read_str(s).map_err(Into::into),
-            None => {
+        match self.source {
+            StrSource::Tag(s) => self.de.read_str(s).map_err(Into::into),
+            StrSource::ReadTag => {
                 let tag = self.de.read_tag()?;
                 match tag {
                     TypeTag::Str(s) => self.de.read_str(s).map_err(Into::into),
                     _ => Err(DeserializeError::Expected("str", tag.into())),
                 }
             }
+            StrSource::Read(s) => Ok(s),
         }
     }
 }
@@ -1143,9 +2243,15 @@ struct MapAccess<'a, R: io::Read> {
     level: usize,
 
     string_keys: bool,
+
+    /// Type of untagged keys, if this is a primitive key map
+    key_type: Option<KeyType>,
     next_value: bool,
     remaining: Option<usize>,
     done: bool,
+
+    /// Last read key, if it is a string
+    key: Option<Arc<str>>,
 }
 
 impl<'de, R: io::Read> serde::de::MapAccess<'de> for MapAccess<'_, R> {
@@ -1187,13 +2293,30 @@ impl<'de, R: io::Read> serde::de::MapAccess<'de> for MapAccess<'_, R> {
         }
 
         let ret = if self.string_keys {
+            let key = StringDeserializer {
+                de: self.de,
+                source: StrSource::ReadTag,
+            }
+            .read_str()?;
+            self.key = Some(key.clone());
+
             let de = StringDeserializer {
                 de: self.de,
-                str_ty: None,
+                source: StrSource::Read(key),
             };
             seed.deserialize(de)?
+        } else if let Some(key_type) = &self.key_type {
+            self.de.deserialize_primitive_key(key_type, seed)?
         } else {
-            seed.deserialize(&mut *self.de)?
+            let tag = self.de.peek_tag()?;
+            self.de.last_str = None;
+            let key = seed.deserialize(&mut *self.de)?;
+            self.key = match tag {
+                TypeTag::Str(_) => self.de.last_str.take(),
+                TypeTag::EmptyStr => Some("".into()),
+                _ => None,
+            };
+            key
         };
 
         self.next_value = true;
@@ -1224,8 +2347,15 @@ impl<'de, R: io::Read> serde::de::MapAccess<'de> for MapAccess<'_, R> {
         if !self.next_value {
             return Err(DeserializeError::TriedValedGotKey);
         }
-        let res = seed.deserialize(&mut *self.de)?;
+        let res = seed
+            .deserialize(&mut *self.de)
+            .map_err(|e| match (self.string_keys, self.key.as_deref()) {
+                (true, Some(key)) => e.in_field(key),
+                (true, None) => e,
+                (false, key) => e.in_key(key),
+            })?;
         self.next_value = false;
+        self.key = None;
 
         if self.done {
             self.de.level -= 1;
@@ -1233,6 +2363,51 @@ impl<'de, R: io::Read> serde::de::MapAccess<'de> for MapAccess<'_, R> {
 
         Ok(res)
     }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining.map(|rem| rem.min(MAX_PREALLOCATION))
+    }
+}
+
+/// Struct fields read ahead of time, each value as separate raw data
+struct BufferedFieldsAccess<'a, R: io::Read> {
+    de: &'a mut Deserializer<R>,
+    fields: std::vec::IntoIter<(Arc<str>, Vec<u8>)>,
+
+    /// Last read key and its value
+    value: Option<(Arc<str>, Vec<u8>)>,
+}
+
+impl<'de, R: io::Read> serde::de::MapAccess<'de> for BufferedFieldsAccess<'_, R> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        if self.value.is_some() {
+            return Err(DeserializeError::TriedKeyGotValue);
+        }
+
+        let Some((key, value)) = self.fields.next() else {
+            return Ok(None);
+        };
+        let res = seed.deserialize(serde::de::value::StrDeserializer::<Self::Error>::new(&key))?;
+        self.value = Some((key, value));
+        Ok(Some(res))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let (key, value) = self.value.take().ok_or(DeserializeError::TriedValedGotKey)?;
+        self.de.deserialize_nested(&value, seed).map_err(|e| e.in_field(&key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
 }
 
 fn read_check_eq<R: io::Read>(mut reader: R, mut data: &[u8]) -> Result<bool, io::Error> {
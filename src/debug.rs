@@ -0,0 +1,383 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    tag::{FlatTypeTag, KeyType, OptionTag, StructType, TagParameter, TypeTag},
+    varint, MAGIC_HEADER,
+};
+
+/// Hex and text dump of bytes, 16 bytes per row
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, bytes) in self.0.chunks(16).enumerate() {
+            write!(f, "  {:08x}  ", row * 16)?;
+
+            for col in 0..16 {
+                match bytes.get(col) {
+                    Some(b) => write!(f, "{b:02x} ")?,
+                    None => f.write_str("   ")?,
+                }
+                if col % 4 == 3 {
+                    f.write_str(" ")?;
+                }
+            }
+
+            for (col, b) in bytes.iter().enumerate() {
+                let char = char::from_u32(*b as u32).unwrap_or('.');
+                let char = if char.is_control() { '.' } else { char };
+                write!(f, "{char}")?;
+                if col % 8 == 7 {
+                    f.write_str(" ")?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes interpreted as consecutive varints, one line per varint with its offset, bytes, and unsigned and signed value.<br>
+/// For making sense of data at an offset where reading failed, offsets are shown relative to the start of the whole data
+pub struct VarintDump<'a> {
+    data: &'a [u8],
+    offset: usize,
+    limit: usize,
+}
+
+impl<'a> VarintDump<'a> {
+    /// Dump of varints starting at `offset` in `data`, up to the end of data or the first varint that can't be decoded
+    pub fn new(data: &'a [u8], offset: usize) -> Self {
+        Self {
+            data,
+            offset,
+            limit: usize::MAX,
+        }
+    }
+
+    /// Dump at most `limit` varints
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl fmt::Display for VarintDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pos = self.offset.min(self.data.len());
+        for _ in 0..self.limit {
+            let bytes = &self.data[pos..];
+            let (Some((unsigned, len)), Some((magnitude, sign, _))) =
+                (varint::decode(bytes), varint::decode_with_sign(bytes))
+            else {
+                if !bytes.is_empty() {
+                    writeln!(f, "{pos:08x}  truncated varint  {}", HexBytes(bytes))?;
+                }
+                break;
+            };
+            let sign = if sign.is_negative() { "-" } else { "" };
+            writeln!(
+                f,
+                "{pos:08x}  {}  unsigned {unsigned}  signed {sign}{magnitude}",
+                HexBytes(&bytes[..len])
+            )?;
+            pos += len;
+        }
+        Ok(())
+    }
+}
+
+/// Dump of serialized data, one line per tag with its offset, name and data bytes.<br>
+/// Keys of primitive key maps are shown as `key`. Stops at the first invalid tag, dumping the remaining bytes
+pub struct AnnotatedDump<'a> {
+    data: &'a [u8],
+    bare: bool,
+}
+
+impl<'a> AnnotatedDump<'a> {
+    /// Dump of data starting with a header, as written by `Serializer::new`
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, bare: false }
+    }
+
+    /// Dump of data without a header, such as `RawValue` bytes
+    pub fn bare(data: &'a [u8]) -> Self {
+        Self { data, bare: true }
+    }
+}
+
+/// Container being walked by AnnotatedDump
+struct DumpFrame {
+    /// Objects left, None if container is ended by End tag
+    remaining: Option<usize>,
+
+    /// Type of untagged keys, if this is a primitive key map
+    key_type: Option<KeyType>,
+    key_next: bool,
+}
+
+struct DumpCursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl DumpCursor<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    /// Read data of a single tag parameter, returning its value if it's a varint
+    fn param(&mut self, param: &TagParameter) -> Option<Option<u64>> {
+        match param {
+            TagParameter::FixedIntBytes(width) => {
+                let end = self.pos + width.bytes();
+                if end > self.data.len() {
+                    return None;
+                }
+                self.pos = end;
+                Some(None)
+            }
+            TagParameter::Varint => {
+                let mut value = 0u64;
+                let mut shift = 0;
+                loop {
+                    let b = self.byte()?;
+                    if shift < 64 {
+                        value |= ((b & 0x7f) as u64) << shift;
+                    }
+                    shift += 7;
+                    if b & 0x80 == 0 {
+                        return Some(Some(value));
+                    }
+                }
+            }
+            TagParameter::VarintLengthPrefixedBytearray => {
+                let len = self.param(&TagParameter::Varint)??;
+                let end = self.pos.checked_add(usize::try_from(len).ok()?)?;
+                if end > self.data.len() {
+                    return None;
+                }
+                self.pos = end;
+                Some(None)
+            }
+        }
+    }
+
+    /// Read all parameters of a tag, returning the last varint, which is the length for containers
+    fn params(&mut self, tag: TypeTag) -> Option<Option<u64>> {
+        let mut last = None;
+        for param in tag.tag_params() {
+            if let Some(v) = self.param(param)? {
+                last = Some(v);
+            }
+        }
+        Some(last)
+    }
+}
+
+/// Single line of AnnotatedDump
+enum DumpEntry<'a> {
+    Header(&'a [u8]),
+    Key { start: usize, depth: usize, bytes: &'a [u8] },
+    KeyTuple { start: usize, depth: usize, bytes: &'a [u8] },
+    Tag { start: usize, depth: usize, tag: FlatTypeTag, bytes: &'a [u8] },
+    InvalidTag { start: usize, byte: u8 },
+    InvalidKeyTag { start: usize, byte: u8 },
+    Truncated { start: usize },
+}
+
+impl<'a> AnnotatedDump<'a> {
+    /// Amount of each tag in the data, by tag name as shown in the dump
+    pub fn tag_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        let _ = self.walk(|entry| {
+            if let DumpEntry::Tag { tag, .. } = entry {
+                *counts.entry(format!("{tag:?}")).or_default() += 1;
+            }
+            Ok(())
+        });
+        counts
+    }
+
+    fn walk(&self, mut visit: impl FnMut(DumpEntry<'a>) -> fmt::Result) -> fmt::Result {
+        let mut cur = DumpCursor {
+            data: self.data,
+            pos: 0,
+        };
+
+        if !self.bare {
+            let header_len = MAGIC_HEADER.len() + 1;
+            let header = &self.data[..header_len.min(self.data.len())];
+            visit(DumpEntry::Header(header))?;
+            cur.pos = header.len();
+        }
+
+        let mut stack: Vec<DumpFrame> = vec![];
+
+        while cur.pos < self.data.len() {
+            while stack.last().is_some_and(|frame| frame.remaining == Some(0)) {
+                stack.pop();
+            }
+
+            let start = cur.pos;
+            let depth = stack.len();
+            let frame = stack.last_mut();
+
+            let key_type = match frame {
+                Some(frame) => {
+                    let key_type = frame.key_type.clone().filter(|_| frame.key_next);
+                    if frame.key_type.is_some() {
+                        frame.key_next = !frame.key_next;
+                    }
+                    key_type
+                }
+                None => None,
+            };
+
+            if let Some(key_type) = key_type {
+                let res = match key_type {
+                    KeyType::Primitive(tag) => cur.params(tag),
+                    KeyType::Tuple(tags) => tags.iter().try_fold(None, |_, tag| cur.params(*tag)),
+                };
+                let bytes = &self.data[start..cur.pos];
+                visit(DumpEntry::Key { start, depth, bytes })?;
+                if res.is_none() {
+                    return visit(DumpEntry::Truncated { start: cur.pos });
+                }
+                if let Some(frame) = stack.last_mut() {
+                    frame.remaining = frame.remaining.map(|r| r - 1);
+                }
+                continue;
+            }
+
+            let Some(byte) = cur.byte() else { break };
+            let Ok(flat) = FlatTypeTag::try_from(byte) else {
+                return visit(DumpEntry::InvalidTag { start, byte });
+            };
+            let tag: TypeTag = flat.into();
+
+            let len = cur.params(tag);
+            let bytes = &self.data[start + 1..cur.pos];
+            visit(DumpEntry::Tag { start, depth, tag: flat, bytes })?;
+            let Some(len) = len else {
+                return visit(DumpEntry::Truncated { start: cur.pos });
+            };
+            let len = len.and_then(|l| usize::try_from(l).ok());
+
+            if let TypeTag::End = tag {
+                if stack.last().is_some_and(|frame| frame.remaining.is_none()) {
+                    stack.pop();
+                }
+                continue;
+            }
+
+            if let Some(frame) = stack.last_mut() {
+                frame.remaining = frame.remaining.map(|r| r - 1);
+            }
+
+            let (remaining, key_type) = match tag {
+                TypeTag::Option(OptionTag::Some)
+                | TypeTag::Struct(StructType::Newtype)
+                | TypeTag::EnumVariant { ty: StructType::Newtype, .. } => (Some(1), None),
+
+                TypeTag::Seq { has_length: true }
+                | TypeTag::Tuple
+                | TypeTag::Struct(StructType::Tuple)
+                | TypeTag::EnumVariant { ty: StructType::Tuple, .. } => (len, None),
+
+                TypeTag::Struct(StructType::Struct)
+                | TypeTag::EnumVariant { ty: StructType::Struct, .. }
+                | TypeTag::Map { has_length: true } => (len.map(|l| l.saturating_mul(2)), None),
+
+                TypeTag::PrimitiveKeyMap => {
+                    let key_byte = self.data[cur.pos - 1];
+                    let Ok(key_tag) = FlatTypeTag::try_from(key_byte) else {
+                        return visit(DumpEntry::InvalidKeyTag { start: cur.pos - 1, byte: key_byte });
+                    };
+                    let key_type = match key_tag.into() {
+                        TypeTag::Tuple => {
+                            let start = cur.pos;
+                            let Some(Some(count)) = cur.param(&TagParameter::Varint) else {
+                                return visit(DumpEntry::Truncated { start });
+                            };
+                            let mut tags = vec![];
+                            for _ in 0..count {
+                                let start = cur.pos;
+                                let Some(byte) = cur.byte() else {
+                                    return visit(DumpEntry::Truncated { start });
+                                };
+                                let Ok(tag) = FlatTypeTag::try_from(byte) else {
+                                    return visit(DumpEntry::InvalidKeyTag { start, byte });
+                                };
+                                tags.push(tag.into());
+                            }
+                            visit(DumpEntry::KeyTuple { start, bytes: &self.data[start..cur.pos], depth })?;
+                            KeyType::Tuple(tags.into_boxed_slice())
+                        }
+                        tag => KeyType::Primitive(tag),
+                    };
+                    (len.map(|l| l.saturating_mul(2)), Some(key_type))
+                }
+
+                TypeTag::Seq { has_length: false } | TypeTag::Map { has_length: false } => {
+                    stack.push(DumpFrame {
+                        remaining: None,
+                        key_type: None,
+                        key_next: false,
+                    });
+                    continue;
+                }
+
+                _ => continue,
+            };
+
+            stack.push(DumpFrame {
+                remaining,
+                key_next: key_type.is_some(),
+                key_type,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for AnnotatedDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.walk(|entry| match entry {
+            DumpEntry::Header(header) => writeln!(f, "{:08x}  header  {}", 0, HexBytes(header)),
+            DumpEntry::Key { start, depth, bytes } => {
+                writeln!(f, "{start:08x}  {:indent$}key  {}", "", HexBytes(bytes), indent = depth * 2)
+            }
+            DumpEntry::KeyTuple { start, depth, bytes } => {
+                writeln!(f, "{start:08x}  {:indent$}key tuple  {}", "", HexBytes(bytes), indent = depth * 2 + 2)
+            }
+            DumpEntry::Tag { start, depth, tag, bytes } => {
+                writeln!(f, "{start:08x}  {:indent$}{tag:?}  {}", "", HexBytes(bytes), indent = depth * 2)
+            }
+            DumpEntry::InvalidTag { start, byte } => {
+                writeln!(f, "{start:08x}  invalid tag {byte}, remaining data:")?;
+                write!(f, "{}", HexDump(&self.data[start..]))
+            }
+            DumpEntry::InvalidKeyTag { start, byte } => writeln!(f, "{start:08x}  invalid key tag {byte}"),
+            DumpEntry::Truncated { start } => writeln!(f, "{start:08x}  truncated data"),
+        })
+    }
+}
+
+struct HexBytes<'a>(&'a [u8]);
+
+impl fmt::Display for HexBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+
+/// Copy exactly `amount` bytes from `src` to `dst`, using a stack buffer of `BUF_SIZE` bytes.<br>
+/// Errors with `UnexpectedEof` if `src` ends before `amount` bytes were copied
+pub fn copy_data<const BUF_SIZE: usize, S: io::Read, D: io::Write>(
+    src: &mut S,
+    dst: &mut D,
+    mut amount: usize,
+) -> Result<(), io::Error> {
+    let mut buf = [0u8; BUF_SIZE];
+    while amount > 0 {
+        let size = amount.min(BUF_SIZE);
+        let slice = &mut buf[..size];
+
+        let read = src.read(slice)?;
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "EOF while copying data",
+            ));
+        }
+        let slice = &slice[..read];
+        dst.write_all(slice)?;
+
+        amount -= read;
+    }
+
+    Ok(())
+}
+
+/// Writer wrapper counting amount of bytes written
+pub struct CountingWriter<W: io::Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: io::Write> CountingWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, written: 0 }
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -1,7 +1,33 @@
+#[cfg(feature = "async")]
+pub mod aio;
+pub mod bytes;
+#[cfg(feature = "checksum")]
+pub mod checksum;
+pub mod columnar;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub mod compress;
+#[cfg(feature = "json")]
+pub mod convert;
 pub mod de;
+pub mod debug;
+pub mod io_util;
 mod macros;
+pub mod measure;
+#[cfg(feature = "bytemuck")]
+pub mod pod;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod prelude;
+pub mod profile;
+pub mod schema;
 pub mod ser;
+#[cfg(all(feature = "store", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub mod store;
+pub mod timestamp;
+pub mod value;
 pub mod varint;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[cfg(test)]
 mod tests;
@@ -10,7 +36,12 @@ mod tag;
 
 use std::{io, ops::Deref, sync::Arc};
 
+// Files are not available on wasm32-unknown-unknown
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::{fs::File, io::Write, path::Path};
+
 use de::DeserializeError;
+use io_util::CountingWriter;
 use ser::SerializeError;
 use serde::{de::DeserializeOwned, Serialize};
 
@@ -18,10 +49,41 @@ pub use ser::Serializer;
 pub use de::Deserializer;
 pub use raw::RawValue;
 
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
+
 const MAGIC_HEADER: &[u8] = b"sd";
 
 const FORMAT_VERSION: u8 = 0;
 
+/// Bit of the version byte set when a header flags byte follows it
+const HEADER_FLAGS_BIT: u8 = 0x80;
+
+/// Bit of the header flags byte set when data is followed by a checksum trailer, other bits hold the compression id
+const HEADER_CHECKSUM_FLAG: u8 = 0x80;
+
+/// Compression id of zstd with an external dictionary, whose id follows the header flags byte as a little-endian `u32`
+const COMPRESSION_ZSTD_DICTIONARY: u8 = 3;
+
+// Read values and errors are often moved between loader threads, keep them `Send + Sync`
+assert_impl!(RawValue: Send + Sync + 'static);
+assert_impl!(SerializeError: std::error::Error + Send + Sync + 'static);
+assert_impl!(DeserializeError: std::error::Error + Send + Sync + 'static);
+assert_impl!(de::DeserializerInitError: std::error::Error + Send + Sync + 'static);
+assert_impl!(Serializer<Vec<u8>>: Send);
+assert_impl!(Deserializer<&[u8]>: Send);
+
+// Callbacks are stored as trait objects, which must stay object-safe and shareable
+assert_impl!(de::StringHook: Send);
+assert_impl!(de::ContinueCheck: Send);
+assert_impl!(de::BlobLoader: Send + Sync);
+assert_impl!(ser::BlobStore: Send + Sync);
+
+/// Max cached string lengths tried by [`to_writer_tuned_cache`]
+const TUNED_CACHE_STR_LENS: &[usize] = &[0, 8, 16, 32, 64, 128, 255, usize::MAX];
+
 enum MaybeArcStr<'a> {
     Arc(Arc<str>),
     Str(&'a str),
@@ -59,6 +121,126 @@ impl<'a> From<MaybeArcStr<'a>> for Arc<str> {
     }
 }
 
+/// How NaN and infinite float values are handled while serializing and deserializing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatPolicy {
+    /// Keep float values as-is, including NaN payload bits
+    #[default]
+    Preserve,
+
+    /// Replace every NaN with a single canonical NaN bit pattern
+    CanonicalNaN,
+
+    /// Error on NaN and infinite values
+    FiniteOnly,
+}
+
+impl FloatPolicy {
+    /// Returns None if value is rejected by the policy
+    #[inline]
+    pub fn apply_f32(self, v: f32) -> Option<f32> {
+        match self {
+            FloatPolicy::Preserve => Some(v),
+            FloatPolicy::CanonicalNaN if v.is_nan() => Some(f32::NAN),
+            FloatPolicy::CanonicalNaN => Some(v),
+            FloatPolicy::FiniteOnly => v.is_finite().then_some(v),
+        }
+    }
+
+    /// Returns None if value is rejected by the policy
+    #[inline]
+    pub fn apply_f64(self, v: f64) -> Option<f64> {
+        match self {
+            FloatPolicy::Preserve => Some(v),
+            FloatPolicy::CanonicalNaN if v.is_nan() => Some(f64::NAN),
+            FloatPolicy::CanonicalNaN => Some(v),
+            FloatPolicy::FiniteOnly => v.is_finite().then_some(v),
+        }
+    }
+}
+
+/// Header info of data that looks like a document, see [`sniff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SniffInfo {
+    /// Format version the document was written with
+    pub version: u8,
+
+    /// Id of the compression of data after the header, None if data is not compressed
+    pub compression: Option<u8>,
+
+    /// Id of the external zstd dictionary data is compressed with, see `Serializer::new_compressed_with_dictionary`
+    pub dictionary: Option<u32>,
+
+    /// Whether data is followed by a checksum trailer
+    pub checksum: bool,
+
+    /// Offset of data after the header
+    pub data_offset: usize,
+}
+
+impl SniffInfo {
+    /// Whether the format version can be read by this version of the library.<br>
+    /// Compressed documents also need the compression feature enabled
+    // Format version is currently the lowest one
+    #[allow(clippy::absurd_extreme_comparisons)]
+    pub const fn version_supported(&self) -> bool {
+        self.version <= FORMAT_VERSION
+    }
+}
+
+/// Check whether data starts with a document header, reading only the header.<br>
+/// For telling documents apart from other file types without constructing a Deserializer
+pub const fn sniff(bytes: &[u8]) -> Option<SniffInfo> {
+    let header_len = MAGIC_HEADER.len();
+    if bytes.len() <= header_len || bytes[0] != MAGIC_HEADER[0] || bytes[1] != MAGIC_HEADER[1] {
+        return None;
+    }
+
+    let ver = bytes[header_len];
+    if ver & HEADER_FLAGS_BIT == 0 {
+        return Some(SniffInfo {
+            version: ver,
+            compression: None,
+            dictionary: None,
+            checksum: false,
+            data_offset: header_len + 1,
+        });
+    }
+
+    if bytes.len() <= header_len + 1 {
+        return None;
+    }
+    let flags = bytes[header_len + 1];
+    let compression = flags & !HEADER_CHECKSUM_FLAG;
+    let mut data_offset = header_len + 2;
+    let mut dictionary = None;
+    if compression == COMPRESSION_ZSTD_DICTIONARY {
+        if bytes.len() < data_offset + 4 {
+            return None;
+        }
+        let id = [bytes[data_offset], bytes[data_offset + 1], bytes[data_offset + 2], bytes[data_offset + 3]];
+        dictionary = Some(u32::from_le_bytes(id));
+        data_offset += id.len();
+    }
+    Some(SniffInfo {
+        version: ver & !HEADER_FLAGS_BIT,
+        compression: if compression == 0 { None } else { Some(compression) },
+        dictionary,
+        checksum: flags & HEADER_CHECKSUM_FLAG != 0,
+        data_offset,
+    })
+}
+
+/// Join a path segment and a path inside of it.<br>
+/// Segments are field names, `[index]` of sequence elements or `[key]` of map values
+pub(crate) fn prepend_path(segment: &str, path: &str) -> String {
+    if path.is_empty() || path.starts_with('[') {
+        format!("{segment}{path}")
+    } else {
+        format!("{segment}.{path}")
+    }
+}
+
 
 
 /// Serialize data into a writer.<br>
@@ -68,6 +250,64 @@ pub fn to_writer<T: Serialize, W: io::Write>(data: &T, writer: W) -> Result<(),
     data.serialize(&mut ser)
 }
 
+/// Serialize data into a borrowed writer, returning amount of bytes written.<br>
+/// Writer is not consumed, so many values can be written into it one after another
+pub fn to_writer_mut<T: Serialize, W: io::Write>(
+    data: &T,
+    writer: &mut W,
+) -> Result<usize, SerializeError> {
+    let mut writer = CountingWriter::new(writer);
+    to_writer(data, &mut writer)?;
+    Ok(writer.written())
+}
+
+/// Serialize data into a writer trait object.<br>
+/// Unlike [`to_writer`], serializer code is generated once for all writer types instead of once per writer type,
+/// trading some speed for smaller binaries. Prefer for rarely serialized types
+pub fn to_dyn_writer<T: Serialize>(data: &T, writer: &mut dyn io::Write) -> Result<(), SerializeError> {
+    to_writer(data, writer)
+}
+
+/// Serialize data into a writer, choosing the max cached string length that produces the smallest output.<br>
+/// Searches only the string cache threshold, integer encodings are already chosen per value.
+/// Data is serialized once for every candidate length while only counting bytes, then once more into the writer,
+/// prefer for archival data where serialization time matters less than size
+pub fn to_writer_tuned_cache<T: Serialize, W: io::Write>(
+    data: &T,
+    writer: W,
+) -> Result<(), SerializeError> {
+    let mut best = None;
+
+    for &max_cache_str_len in TUNED_CACHE_STR_LENS {
+        let mut counter = CountingWriter::new(io::sink());
+        data.serialize(&mut ser::Serializer::new(&mut counter, max_cache_str_len)?)?;
+
+        if best.is_none_or(|(size, _)| counter.written() < size) {
+            best = Some((counter.written(), max_cache_str_len));
+        }
+    }
+
+    let max_cache_str_len = best.map_or(255, |(_, len)| len);
+    let mut ser = ser::Serializer::new(writer, max_cache_str_len)?;
+    data.serialize(&mut ser)
+}
+
+/// Serialize data into a Vec of bytes, choosing the max cached string length that produces the smallest output.<br>
+/// See [`to_writer_tuned_cache`]
+pub fn to_bytes_tuned_cache<T: Serialize>(data: &T) -> Result<Vec<u8>, SerializeError> {
+    let mut vec = vec![];
+    to_writer_tuned_cache(data, &mut vec)?;
+    Ok(vec)
+}
+
+/// Amount of bytes data serializes into, including the header, without writing it anywhere.<br>
+/// For preallocating buffers or writing length prefixes before the data
+pub fn serialized_size<T: Serialize>(data: &T) -> Result<usize, SerializeError> {
+    let mut counter = CountingWriter::new(io::sink());
+    to_writer(data, &mut counter)?;
+    Ok(counter.written())
+}
+
 /// Serialize data into a Vec of bytes.
 pub fn to_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>, SerializeError> {
     let mut vec = vec![];
@@ -75,25 +315,175 @@ pub fn to_bytes<T: Serialize>(data: &T) -> Result<Vec<u8>, SerializeError> {
     Ok(vec)
 }
 
+/// Serialize data, appending it to a `BytesMut`, returning amount of bytes written.
+#[cfg(feature = "bytes")]
+pub fn to_bytes_mut<T: Serialize>(data: &T, buf: &mut ::bytes::BytesMut) -> Result<usize, SerializeError> {
+    use ::bytes::BufMut;
+    to_writer_mut(data, &mut buf.writer())
+}
+
+/// Serialize every value of an iterator into a writer one after another, after a single header, returning amount of values written.<br>
+/// Only field and variant names are shared between values, so memory used by string maps at both ends
+/// doesn't grow with the amount of values. Read back with [`read_docs`]
+pub fn write_all_docs<T: Serialize, W: io::Write>(
+    docs: impl IntoIterator<Item = T>,
+    writer: W,
+) -> Result<usize, SerializeError> {
+    let ser = ser::Serializer::new(writer, 255)?.with_intern_policy(ser::InternPolicy::NamesOnly);
+    let mut stream = ser::StreamWriter::from_serializer(ser);
+    for doc in docs {
+        stream.write(&doc)?;
+    }
+    Ok(stream.written())
+}
+
+/// Serialize the default value of a type, such as a save file created on first run.
+pub fn empty_document<T: Default + Serialize>() -> Result<Vec<u8>, SerializeError> {
+    to_bytes(&T::default())
+}
+
+/// Serialize data into a file, creating or truncating it.<br>
+/// Writes are buffered
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn to_file<T: Serialize>(data: &T, path: impl AsRef<Path>) -> Result<(), SerializeError> {
+    let mut writer = io::BufWriter::new(File::create(path)?);
+    to_writer(data, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
 /// Serialize data into a RawValue.
 pub fn to_raw<T: Serialize>(data: &T) -> Result<RawValue, SerializeError> {
     RawValue::serialize_from(data)
 }
 
+/// Serialize data into a dynamic Value.
+pub fn to_value<T: Serialize>(data: &T) -> Result<value::Value, SerializeError> {
+    value::Value::from_raw(&to_raw(data)?).map_err(serde::ser::Error::custom)
+}
+
 /// Deserialize data from a reader.<br>
-/// Reader preferred to be buffered, deserialization does many small reads
+/// Reader preferred to be buffered, deserialization does many small reads.<br>
+/// Checksums of documents written with `Serializer::new_checksummed` are verified after reading them
 pub fn from_reader<T: DeserializeOwned, R: io::Read>(reader: R) -> Result<T, DeserializeError> {
-    let mut de = de::Deserializer::new(reader)?;
-    T::deserialize(&mut de)
+    read_document(de::Deserializer::new(reader)?)
+}
+
+fn read_document<T: DeserializeOwned, R: io::Read>(mut de: de::Deserializer<R>) -> Result<T, DeserializeError> {
+    let value = T::deserialize(&mut de)?;
+    de.finish()?;
+    Ok(value)
+}
+
+/// Deserialize data from a reader trait object.<br>
+/// Like [`to_dyn_writer`], trades some speed for deserializer code generated once for all reader types
+pub fn from_dyn_reader<T: DeserializeOwned>(reader: &mut dyn io::Read) -> Result<T, DeserializeError> {
+    from_reader(reader)
 }
 
-/// Deserialize data from a slice of bytes.
+/// Check that reader contains a single well-formed document without deserializing it.<br>
+/// Tags, lengths, string references, UTF-8 data, checksums and compressed data are checked, but not whether the value matches any particular type,
+/// see [`verify_as`] for that
+pub fn verify<R: io::Read>(reader: R) -> Result<(), DeserializeError> {
+    verify_document(reader, |de| {
+        let mut se = ser::Serializer::new_bare(io::sink(), 0);
+        RawValue::copy_value(de, &mut se)
+    })
+}
+
+/// Check that reader contains a single well-formed document that deserializes as `T`, dropping the read value
+pub fn verify_as<T: DeserializeOwned, R: io::Read>(reader: R) -> Result<(), DeserializeError> {
+    verify_document(reader, |de| T::deserialize(de).map(drop))
+}
+
+fn verify_document<R: io::Read>(
+    mut reader: R,
+    read: impl FnOnce(&mut de::Deserializer<&mut R>) -> Result<(), DeserializeError>,
+) -> Result<(), DeserializeError> {
+    let mut de = de::Deserializer::new_exact(&mut reader)?;
+    read(&mut de)?;
+    de.finish()?;
+    drop(de);
+
+    match reader.read(&mut [0])? {
+        0 => Ok(()),
+        _ => Err(DeserializeError::TrailingData),
+    }
+}
+
+/// Deserialize data from a borrowed reader.<br>
+/// Reader is not consumed and is left right after the read data, so many values can be read from it one after another
+pub fn from_reader_mut<T: DeserializeOwned, R: io::Read>(
+    reader: &mut R,
+) -> Result<T, DeserializeError> {
+    read_document(de::Deserializer::new_exact(reader)?)
+}
+
+/// Deserialize data from a slice of bytes.<br>
+/// Checksums of documents written with `Serializer::new_checksummed` are verified after reading them
 pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DeserializeError> {
-    let cur = std::io::Cursor::new(bytes);
-    from_reader(cur)
+    read_document(de::Deserializer::new(std::io::Cursor::new(bytes))?)
+}
+
+/// Deserialize data from the start of a slice of bytes, also returning amount of bytes read.<br>
+/// Allows reading many values placed one after another in a single buffer
+pub fn from_bytes_prefix<T: DeserializeOwned>(bytes: &[u8]) -> Result<(T, usize), DeserializeError> {
+    let mut cur = std::io::Cursor::new(bytes);
+    let value = from_reader_mut(&mut cur)?;
+    Ok((value, cur.position() as usize))
+}
+
+/// Deserialize data from the start of a `Bytes`, advancing it past the read data.<br>
+/// Data after the value is kept without copying, so many values can be read from a single received buffer
+#[cfg(feature = "bytes")]
+pub fn from_bytes_buf<T: DeserializeOwned>(buf: &mut ::bytes::Bytes) -> Result<T, DeserializeError> {
+    use ::bytes::Buf;
+    let (value, read) = from_bytes_prefix(buf)?;
+    buf.advance(read);
+    Ok(value)
+}
+
+/// Iterate over values written by [`write_all_docs`], deserializing them one at a time until the reader ends.<br>
+/// Reader preferred to be buffered. Strings are kept for later values, see [`de::StreamReader`]
+pub fn read_docs<T: DeserializeOwned, R: io::Read>(reader: R) -> Result<de::StreamReader<T, R>, DeserializeError> {
+    Ok(de::StreamReader::new(de::Deserializer::new(reader)?))
+}
+
+/// Deserialize data from a file.<br>
+/// Reads are buffered
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn from_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, DeserializeError> {
+    from_reader(io::BufReader::new(File::open(path)?))
+}
+
+/// Deserialize data from a file, returning the default value if the file doesn't exist.<br>
+/// Other errors, such as a corrupted file, are still returned
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn from_file_or_default<T: DeserializeOwned + Default>(path: impl AsRef<Path>) -> Result<T, DeserializeError> {
+    match File::open(path) {
+        Ok(file) => from_reader(io::BufReader::new(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Compare two documents structurally, ignoring differences in string interning and tags used for the same values.<br>
+/// Integers of different widths or signedness with the same value are equal, floats are compared by value, so NaN never equals
+pub fn eq_documents(a: impl io::Read, b: impl io::Read) -> Result<bool, DeserializeError> {
+    let mut a = value::Value::read(&mut de::Deserializer::new(a)?)?;
+    let mut b = value::Value::read(&mut de::Deserializer::new(b)?)?;
+    a.normalize_ints();
+    b.normalize_ints();
+    Ok(a == b)
 }
 
 /// Deserialize data from a RawValue.
 pub fn from_raw<T: DeserializeOwned>(raw: &RawValue) -> Result<T, DeserializeError> {
     raw.deserialize_into()
+}
+
+/// Deserialize data from a dynamic Value.
+pub fn from_value<T: DeserializeOwned>(value: &value::Value) -> Result<T, DeserializeError> {
+    let raw = value.to_raw().map_err(<DeserializeError as serde::de::Error>::custom)?;
+    from_raw(&raw)
 }
\ No newline at end of file
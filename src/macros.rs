@@ -43,6 +43,7 @@ macro_rules! define_tag {
 
             pub const ALL: &[Self] = &[$(Self::$membername),*];
 
+            #[inline]
             pub const fn unpack(self) -> $unpackty {
                 match self {
                     $(
@@ -53,6 +54,7 @@ macro_rules! define_tag {
         }
 
         impl $unpackty {
+            #[inline]
             pub const fn pack(self) -> $name {
                 match self {
                     $(
@@ -63,18 +65,21 @@ macro_rules! define_tag {
         }
 
         impl From<$name> for $unpackty {
+            #[inline]
             fn from(value: $name) -> $unpackty {
                 value.unpack()
             }
         }
 
         impl From<$unpackty> for $name {
+            #[inline]
             fn from(value: $unpackty) -> $name {
                value.pack()
             }
         }
 
         impl From<$name> for $reprty {
+            #[inline]
             fn from(value: $name) -> $reprty {
                 match value {
                     $(
@@ -87,6 +92,7 @@ macro_rules! define_tag {
         impl TryFrom<$reprty> for $name {
             type Error = $reprty;
 
+            #[inline]
             fn try_from(value: $reprty) -> Result<$name, $reprty> {
                 match value {
                     $($membervalue => Ok($name::$membername),)*
@@ -96,3 +102,98 @@ macro_rules! define_tag {
         }
     };
 }
+
+/// Define a fieldless enum serialized as its `u32` discriminant instead of the variant name.<br>
+/// Smaller and faster than names, but reordering or renumbering variants breaks existing data.<br>
+/// Also implements `From<Enum> for u32` and `TryFrom<u32> for Enum`, which returns invalid values as errors
+#[macro_export]
+macro_rules! repr_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$membermeta:meta])*
+                $membername:ident $(= $membervalue:expr)?
+            ),*
+
+            $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(u32)]
+        $vis enum $name {
+            $(
+                $(#[$membermeta])*
+                $membername $(= $membervalue)?,
+            )*
+        }
+
+        #[automatically_derived]
+        impl ::core::convert::From<$name> for u32 {
+            fn from(value: $name) -> u32 {
+                match value {
+                    $(
+                        $name::$membername => $name::$membername as u32,
+                    )*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl ::core::convert::TryFrom<u32> for $name {
+            type Error = u32;
+
+            fn try_from(value: u32) -> ::core::result::Result<$name, u32> {
+                $(
+                    if value == $name::$membername as u32 {
+                        return ::core::result::Result::Ok($name::$membername);
+                    }
+                )*
+                ::core::result::Result::Err(value)
+            }
+        }
+
+        #[automatically_derived]
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__private::serde::Serializer,
+            {
+                let value = match self {
+                    $(
+                        $name::$membername => $name::$membername as u32,
+                    )*
+                };
+                serializer.serialize_u32(value)
+            }
+        }
+
+        #[automatically_derived]
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+            {
+                let value = <u32 as $crate::__private::serde::Deserialize>::deserialize(deserializer)?;
+                <$name as ::core::convert::TryFrom<u32>>::try_from(value).map_err(|v| {
+                    <D::Error as $crate::__private::serde::de::Error>::custom(::core::format_args!(
+                        "invalid {} discriminant {v}",
+                        ::core::stringify!($name)
+                    ))
+                })
+            }
+        }
+    };
+}
+
+/// Compile-time check that a type implements the given traits, for keeping public types usable across threads.<br>
+/// `smoldata::assert_impl!(RawValue: Send + Sync + 'static);`
+#[macro_export]
+macro_rules! assert_impl {
+    ($ty:ty: $($bound:tt)+) => {
+        const _: () = {
+            const fn assert_impl<T: ?Sized + $($bound)+>() {}
+            assert_impl::<$ty>();
+        };
+    };
+}
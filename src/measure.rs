@@ -0,0 +1,177 @@
+use std::{collections::HashMap, fmt, io};
+
+use crate::{
+    de::{DeserializeError, Deserializer},
+    tag::{OptionTag, StructType, TypeTag},
+    varint,
+};
+
+/// Deepest path measured separately, deeper values are counted as part of their parent at this depth
+const MAX_DEPTH: usize = 64;
+
+/// Size of all values at a single path
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PathSize {
+    /// How many values were found at this path
+    pub count: usize,
+
+    /// Total amount of bytes of values at this path, including nested values
+    pub bytes: u64,
+}
+
+/// Sizes of a document's values grouped by their path, created by [`measure`].<br>
+/// Paths are field names joined by `.`, with `[]` for elements of sequences and tuples and values of maps,
+/// so `players[].inventory` holds inventories of all players. The root value has an empty path
+#[derive(Debug, Default, Clone)]
+pub struct SizeReport {
+    paths: HashMap<String, PathSize>,
+}
+
+impl SizeReport {
+    pub fn get(&self, path: &str) -> Option<&PathSize> {
+        self.paths.get(path)
+    }
+
+    /// Recorded paths, sorted by size, largest first
+    pub fn entries(&self) -> Vec<(&str, PathSize)> {
+        let mut entries: Vec<_> = self.paths.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        entries.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        entries
+    }
+
+    /// `n` largest paths, largest first
+    pub fn heaviest(&self, n: usize) -> Vec<(&str, PathSize)> {
+        let mut entries = self.entries();
+        entries.truncate(n);
+        entries
+    }
+
+    fn record(&mut self, path: &str, bytes: u64) {
+        let entry = self.paths.entry(path.into()).or_default();
+        entry.count += 1;
+        entry.bytes += bytes;
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, size) in self.entries() {
+            let path = if path.is_empty() { "(root)" } else { path };
+            writeln!(f, "{path}: {} values, {} bytes", size.count, size.bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a document and attribute its bytes to field paths, for finding what takes up space in it
+pub fn measure(data: &[u8]) -> Result<SizeReport, DeserializeError> {
+    measure_reader(data)
+}
+
+/// Read a document from a reader and attribute its bytes to field paths.<br>
+/// Reader preferred to be buffered
+pub fn measure_reader<R: io::Read>(reader: R) -> Result<SizeReport, DeserializeError> {
+    let mut de = Deserializer::new(reader)?;
+    let mut report = SizeReport::default();
+    measure_value(&mut de, &mut report, &mut String::new(), 0)?;
+    Ok(report)
+}
+
+fn measure_value<R: io::Read>(
+    de: &mut Deserializer<R>,
+    report: &mut SizeReport,
+    path: &mut String,
+    depth: usize,
+) -> Result<(), DeserializeError> {
+    let start = de.position();
+
+    if depth >= MAX_DEPTH {
+        de.skip_value()?;
+        report.record(path, de.position() - start);
+        return Ok(());
+    }
+
+    // Wrappers are measured together with the wrapped value
+    loop {
+        match de.peek_tag()? {
+            TypeTag::Option(OptionTag::Some) | TypeTag::Struct(StructType::Newtype) => {
+                de.peek_tag_consume();
+            }
+            TypeTag::EnumVariant {
+                ty: StructType::Newtype,
+                str,
+            } => {
+                de.peek_tag_consume();
+                de.read_str(str)?;
+            }
+            _ => break,
+        }
+    }
+
+    match de.peek_tag()? {
+        tag @ (TypeTag::Struct(StructType::Struct) | TypeTag::EnumVariant { ty: StructType::Struct, .. }) => {
+            de.peek_tag_consume();
+            if let Some(str) = tag.get_str() {
+                de.read_str(str)?;
+            }
+
+            let len = varint::read_length(&mut de.reader)?;
+            for _ in 0..len {
+                let field = match de.read_tag()? {
+                    TypeTag::Str(str) => de.read_str(str)?,
+                    tag => return Err(DeserializeError::Expected("field name", tag.into())),
+                };
+
+                let parent_len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&field);
+                measure_value(de, report, path, depth + 1)?;
+                path.truncate(parent_len);
+            }
+        }
+
+        tag @ (TypeTag::Struct(StructType::Tuple)
+        | TypeTag::Tuple
+        | TypeTag::Seq { .. }
+        | TypeTag::Map { .. }
+        | TypeTag::EnumVariant { ty: StructType::Tuple, .. }) => {
+            de.peek_tag_consume();
+            if let Some(str) = tag.get_str() {
+                de.read_str(str)?;
+            }
+
+            let is_map = matches!(tag, TypeTag::Map { .. });
+            let values = match tag {
+                TypeTag::Seq { has_length: false } | TypeTag::Map { has_length: false } => None,
+                TypeTag::Map { has_length: true } => Some(varint::read_length(&mut de.reader)?.saturating_mul(2)),
+                _ => Some(varint::read_length(&mut de.reader)?),
+            };
+
+            // Map keys are counted as part of the map, values are measured as its elements
+            let parent_len = path.len();
+            path.push_str("[]");
+            let mut index = 0;
+            while values.is_none_or(|v| index < v) {
+                if values.is_none() && matches!(de.peek_tag()?, TypeTag::End) {
+                    de.peek_tag_consume();
+                    break;
+                }
+                if is_map && index % 2 == 0 {
+                    de.skip_value()?;
+                } else {
+                    measure_value(de, report, path, depth + 1)?;
+                }
+                index += 1;
+            }
+            path.truncate(parent_len);
+        }
+
+        // Primitive key maps have untagged keys, so they're measured only as a whole
+        _ => de.skip_value()?,
+    }
+
+    report.record(path, de.position() - start);
+    Ok(())
+}
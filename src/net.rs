@@ -0,0 +1,424 @@
+use std::{
+    io::{self, Read, SeekFrom, Write},
+    marker::PhantomData,
+    slice,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    de::DeserializeError,
+    io_util::CountingWriter,
+    ser::SerializeError,
+    varint::{self, VarIntReadError},
+};
+
+/// Max bytes a varint frame length can take, enough for any `u64`
+const MAX_LENGTH_BYTES: usize = 10;
+
+/// Magic bytes ending a frame index footer
+const INDEX_MAGIC: &[u8; 4] = b"sdix";
+
+/// Length of a frame index footer: index length, frames length and magic bytes
+const INDEX_FOOTER_LEN: u64 = 8 + 8 + INDEX_MAGIC.len() as u64;
+
+/// Indexed document: its number, offset of its frame from the start of frames and its key
+type IndexEntry = (u64, u64, Option<i64>);
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+
+    #[error("Frame of {len} bytes is larger than max frame size of {max} bytes")]
+    TooLarge { len: usize, max: usize },
+
+    #[error("Invalid frame length")]
+    InvalidLength(#[source] VarIntReadError),
+
+    #[error(transparent)]
+    Serialize(#[from] SerializeError),
+
+    #[error(transparent)]
+    Deserialize(#[from] DeserializeError),
+
+    #[error("Data doesn't end with a frame index")]
+    MissingIndex,
+}
+
+/// Splits a stream of bytes into smoldata documents, each prefixed with its varint encoded length.<br>
+/// Doesn't depend on any async runtime, bytes can come from any source, such as a tokio `BytesMut` or a socket read loop
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCodec {
+    max_size: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameCodec {
+    /// Codec with max frame size of 8 MiB
+    pub fn new() -> Self {
+        Self {
+            max_size: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Set max size of a single frame, not including the length prefix.<br>
+    /// Larger frames are rejected with [`FrameError::TooLarge`] both when encoding and decoding
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Serialize data as a frame, appending it to `buf`
+    pub fn encode<T: Serialize>(&self, data: &T, buf: &mut Vec<u8>) -> Result<(), FrameError> {
+        let doc = crate::to_bytes(data)?;
+        self.check_len(doc.len())?;
+        varint::write_unsigned_varint(&mut *buf, doc.len())?;
+        buf.extend_from_slice(&doc);
+        Ok(())
+    }
+
+    /// Deserialize the first frame in `buf`, removing it from the buffer.<br>
+    /// Returns None if `buf` doesn't hold a full frame yet, so more data should be read into it
+    pub fn decode<T: DeserializeOwned>(&self, buf: &mut Vec<u8>) -> Result<Option<T>, FrameError> {
+        let Some((start, end)) = self.split(buf)? else {
+            return Ok(None);
+        };
+        let value = crate::from_bytes(&buf[start..end]);
+        buf.drain(..end);
+        Ok(Some(value?))
+    }
+
+    /// Find the first frame in `buf` without consuming it.<br>
+    /// Returns range of frame data and None if `buf` doesn't hold a full frame yet
+    pub fn split(&self, buf: &[u8]) -> Result<Option<(usize, usize)>, FrameError> {
+        let prefix = &buf[..buf.len().min(MAX_LENGTH_BYTES)];
+        let Some(prefix_len) = prefix.iter().position(|b| b & 0x80 == 0).map(|i| i + 1) else {
+            if prefix.len() == MAX_LENGTH_BYTES {
+                return Err(FrameError::InvalidLength(VarIntReadError::ValueTooBig));
+            }
+            return Ok(None);
+        };
+
+        let len = varint::read_length(prefix).map_err(FrameError::InvalidLength)?;
+        self.check_len(len)?;
+
+        // Only reachable with huge max sizes, such frames can't exist anyway
+        let end = prefix_len.checked_add(len).ok_or(FrameError::TooLarge {
+            len,
+            max: self.max_size,
+        })?;
+        if buf.len() < end {
+            return Ok(None);
+        }
+        Ok(Some((prefix_len, end)))
+    }
+
+    /// Serialize data as a single frame into a writer
+    pub fn write<T: Serialize, W: io::Write>(&self, data: &T, mut writer: W) -> Result<(), FrameError> {
+        let mut buf = vec![];
+        self.encode(data, &mut buf)?;
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Read a single frame from a reader, blocking until all of it is read.<br>
+    /// Frame length is checked before reading its data
+    pub fn read<T: DeserializeOwned, R: io::Read>(&self, mut reader: R) -> Result<T, FrameError> {
+        let len = Self::read_length(&mut reader)?;
+        self.read_data(len, reader)
+    }
+
+    /// Read length of the next frame from a reader
+    fn read_length<R: io::Read>(reader: R) -> Result<usize, FrameError> {
+        match varint::read_length(reader) {
+            Ok(len) => Ok(len),
+            Err(VarIntReadError::IOError(e)) => Err(e.into()),
+            Err(e) => Err(FrameError::InvalidLength(e)),
+        }
+    }
+
+    /// Read frame data of a frame with already read length
+    fn read_data<T: DeserializeOwned, R: io::Read>(&self, len: usize, mut reader: R) -> Result<T, FrameError> {
+        self.check_len(len)?;
+
+        let mut doc = vec![0; len];
+        reader.read_exact(&mut doc)?;
+        Ok(crate::from_bytes(&doc)?)
+    }
+
+    pub(crate) fn check_len(&self, len: usize) -> Result<(), FrameError> {
+        if len > self.max_size {
+            return Err(FrameError::TooLarge {
+                len,
+                max: self.max_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Serialize data as a single frame into a writer, see [`FrameCodec`]
+pub fn write_framed_into<T: Serialize, W: io::Write>(data: &T, writer: W) -> Result<(), FrameError> {
+    FrameCodec::new().write(data, writer)
+}
+
+/// Read a single frame from a reader, see [`FrameCodec`]
+pub fn read_framed_from<T: DeserializeOwned, R: io::Read>(reader: R) -> Result<T, FrameError> {
+    FrameCodec::new().read(reader)
+}
+
+/// Iterator reading frames from a reader, such as a `TcpStream`, until it ends.<br>
+/// Reads block until a whole frame is received. Reader ending between frames or an empty frame, such as one
+/// written by [`IndexedFrameWriter`] before its index, ends the iterator, ending in the middle of a frame is an error.
+/// Stops after the first error
+pub struct FrameReader<T, R: io::Read> {
+    reader: R,
+    codec: FrameCodec,
+    done: bool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, R: io::Read> FrameReader<T, R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_codec(reader, FrameCodec::new())
+    }
+
+    /// Reader using a configured codec, such as one with a different max frame size
+    pub fn with_codec(reader: R, codec: FrameCodec) -> Self {
+        Self {
+            reader,
+            codec,
+            done: false,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read first byte of the next frame, None if the reader has ended
+    fn read_first_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = 0u8;
+        loop {
+            match self.reader.read(slice::from_mut(&mut byte)) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<T>, FrameError>
+    where
+        T: DeserializeOwned,
+    {
+        let Some(first) = self.read_first_byte()? else {
+            return Ok(None);
+        };
+        let len = FrameCodec::read_length(slice::from_ref(&first).chain(&mut self.reader))?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.codec.read_data(len, &mut self.reader).map(Some)
+    }
+}
+
+impl<T: DeserializeOwned, R: io::Read> Iterator for FrameReader<T, R> {
+    type Item = Result<T, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.read_frame().transpose();
+        self.done = !matches!(res, Some(Ok(_)));
+        res
+    }
+}
+
+/// Writer of framed documents followed by an index of their offsets, so documents can be read by their number
+/// or key without reading all documents before them, see [`IndexedFrameReader`].<br>
+/// Every `interval`-th document is indexed, documents between are reached by skipping frames after the nearest indexed one.
+/// Frames are ended by an empty frame, so [`FrameReader`] reads them as usual and stops before the index.
+/// Documents are framed separately, so no string map state is needed to start reading from any of them
+pub struct IndexedFrameWriter<W: io::Write> {
+    writer: CountingWriter<W>,
+    codec: FrameCodec,
+    interval: u64,
+    count: u64,
+    entries: Vec<IndexEntry>,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> IndexedFrameWriter<W> {
+    /// Writer indexing every 64th document
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: CountingWriter::new(writer),
+            codec: FrameCodec::new(),
+            interval: 64,
+            count: 0,
+            entries: vec![],
+            buf: vec![],
+        }
+    }
+
+    /// Writer using a configured codec, such as one with a different max frame size
+    pub fn with_codec(mut self, codec: FrameCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Set how often documents are indexed, smaller intervals make reading faster and the index larger
+    pub fn with_interval(mut self, interval: u64) -> Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// Write a document as a frame
+    pub fn write<T: Serialize>(&mut self, data: &T) -> Result<(), FrameError> {
+        self.write_entry(data, None)
+    }
+
+    /// Write a document with a key, such as a timestamp, for finding it with [`IndexedFrameReader::find_key`].<br>
+    /// Keys must not decrease from one document to the next
+    pub fn write_keyed<T: Serialize>(&mut self, data: &T, key: i64) -> Result<(), FrameError> {
+        self.write_entry(data, Some(key))
+    }
+
+    fn write_entry<T: Serialize>(&mut self, data: &T, key: Option<i64>) -> Result<(), FrameError> {
+        self.buf.clear();
+        self.codec.encode(data, &mut self.buf)?;
+
+        if self.count.is_multiple_of(self.interval) {
+            self.entries.push((self.count, self.writer.written() as u64, key));
+        }
+        self.writer.write_all(&self.buf)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write the end of frames and the index, returning the inner writer
+    pub fn finish(mut self) -> Result<W, FrameError> {
+        self.writer.write_all(&[0])?;
+        let frames_len = self.writer.written() as u64;
+
+        let index = crate::to_bytes(&(self.count, &self.entries))?;
+        self.writer.write_all(&index)?;
+        self.writer.write_all(&(index.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&frames_len.to_le_bytes())?;
+        self.writer.write_all(INDEX_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+/// Reader of documents written by [`IndexedFrameWriter`], seeking to them using the index at the end of data
+pub struct IndexedFrameReader<R: io::Read + io::Seek> {
+    reader: R,
+    codec: FrameCodec,
+    start: u64,
+    count: u64,
+    entries: Vec<IndexEntry>,
+}
+
+impl<R: io::Read + io::Seek> IndexedFrameReader<R> {
+    /// Read the index at the end of the reader.<br>
+    /// Frames are expected to end right before the index, but don't have to start at the start of the reader
+    pub fn new(mut reader: R) -> Result<Self, FrameError> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        let footer_start = end.checked_sub(INDEX_FOOTER_LEN).ok_or(FrameError::MissingIndex)?;
+        reader.seek(SeekFrom::Start(footer_start))?;
+
+        let mut footer = [0u8; INDEX_FOOTER_LEN as usize];
+        reader.read_exact(&mut footer)?;
+        let (lens, magic) = footer.split_at(16);
+        if magic != INDEX_MAGIC {
+            return Err(FrameError::MissingIndex);
+        }
+        let index_len = u64::from_le_bytes(lens[..8].try_into().expect("8 bytes"));
+        let frames_len = u64::from_le_bytes(lens[8..].try_into().expect("8 bytes"));
+
+        let start = footer_start
+            .checked_sub(index_len)
+            .and_then(|index_start| index_start.checked_sub(frames_len))
+            .ok_or(FrameError::MissingIndex)?;
+
+        reader.seek(SeekFrom::Start(start + frames_len))?;
+        let mut index = vec![];
+        (&mut reader).take(index_len).read_to_end(&mut index)?;
+        let (count, entries) = crate::from_bytes(&index)?;
+
+        Ok(Self {
+            reader,
+            codec: FrameCodec::new(),
+            start,
+            count,
+            entries,
+        })
+    }
+
+    /// Read documents using a configured codec, such as one with a different max frame size
+    pub fn with_codec(mut self, codec: FrameCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Amount of documents
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Read a document by its number, None if there are not that many documents
+    pub fn read<T: DeserializeOwned>(&mut self, index: u64) -> Result<Option<T>, FrameError> {
+        if index >= self.count {
+            return Ok(None);
+        }
+        self.frames_from(index)?.next().transpose()
+    }
+
+    /// Number of the last indexed document with a key not greater than `key`, for reading documents from it with [`Self::frames_from`].<br>
+    /// Only keys of indexed documents are known, so documents with `key` may be up to an index interval after it
+    pub fn find_key(&self, key: i64) -> Option<u64> {
+        let pos = self.entries.partition_point(|(_, _, entry_key)| entry_key.is_none_or(|k| k <= key));
+        pos.checked_sub(1).map(|pos| self.entries[pos].0)
+    }
+
+    /// Iterate over documents starting from one with number `index`, until the end of frames
+    pub fn frames_from<T: DeserializeOwned>(&mut self, index: u64) -> Result<FrameReader<T, &mut R>, FrameError> {
+        let pos = self.entries.partition_point(|(doc, _, _)| *doc <= index);
+        let (doc, offset) = match pos.checked_sub(1) {
+            Some(pos) => (self.entries[pos].0, self.entries[pos].1),
+            None => (0, 0),
+        };
+
+        self.reader.seek(SeekFrom::Start(self.start + offset))?;
+        for _ in doc..index.min(self.count) {
+            let len = FrameCodec::read_length(&mut self.reader)?;
+            self.codec.check_len(len)?;
+            self.reader.seek(SeekFrom::Current(len as i64))?;
+        }
+        Ok(FrameReader::with_codec(&mut self.reader, self.codec))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
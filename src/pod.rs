@@ -0,0 +1,56 @@
+use std::{
+    io,
+    mem::{size_of, MaybeUninit},
+};
+
+use bytemuck::Pod;
+use serde::{de, Deserialize};
+
+use crate::{bytes::ByteBuf, de::DeserializeError, Deserializer};
+
+/// Serialize a plain-old-data value as a byte array of its memory, for use with `#[serde(with = "smoldata::pod")]`.<br>
+/// Bytes are in native endianness and layout, so data is only readable by builds with the same ones,
+/// such as embedded telemetry structs that are already defined as packed C layouts
+pub fn serialize<T: Pod, S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytemuck::bytes_of(value))
+}
+
+/// Deserialize a plain-old-data value from a byte array of its memory, erroring if its size doesn't match
+pub fn deserialize<'de, T: Pod, D: serde::Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    let bytes = ByteBuf::deserialize(deserializer)?;
+    if bytes.len() != size_of::<T>() {
+        return Err(de::Error::custom(format_args!(
+            "Expected {} bytes of plain-old-data, read {}",
+            size_of::<T>(),
+            bytes.len()
+        )));
+    }
+    Ok(bytemuck::pod_read_unaligned(&bytes))
+}
+
+/// Serialize a slice of plain-old-data values as a byte array of their memory, for use with `#[serde(serialize_with = "smoldata::pod::serialize_slice")]`.<br>
+/// Such arrays are written as-is, and can be read without copying with [`read_into_slice`] and [`read_into_uninit_slice`]
+pub fn serialize_slice<T: Pod, S: serde::Serializer>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytemuck::cast_slice(values))
+}
+
+/// Read a byte array written by [`serialize_slice`] directly into a slice of plain-old-data values.<br>
+/// Array length must match the slice exactly
+pub fn read_into_slice<T: Pod, R: io::Read>(de: &mut Deserializer<R>, buf: &mut [T]) -> Result<(), DeserializeError> {
+    de.read_bytes_into(bytemuck::cast_slice_mut(buf))
+}
+
+/// Read a byte array written by [`serialize_slice`] directly into uninitialized memory, such as a large preallocated buffer.<br>
+/// Buffer is zeroed before reading, since readers can't read into uninitialized memory, which is still cheaper
+/// than reading into a temporary buffer and copying it. Array length must match the buffer exactly
+pub fn read_into_uninit_slice<'b, T: Pod, R: io::Read>(
+    de: &mut Deserializer<R>,
+    buf: &'b mut [MaybeUninit<T>],
+) -> Result<&'b mut [T], DeserializeError> {
+    buf.fill(MaybeUninit::new(T::zeroed()));
+
+    // SAFETY: every element was initialized above, and MaybeUninit<T> has the same layout as T
+    let buf = unsafe { &mut *(buf as *mut [MaybeUninit<T>] as *mut [T]) };
+    read_into_slice(de, buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,36 @@
+pub use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub use crate::{
+    de::{DeserializeError, Deserializer, DuplicateFieldPolicy, StreamReader},
+    ser::{InternPolicy, SerializeError, Serializer, StreamWriter},
+    bytes::{ByteBuf, Bytes},
+    columnar::Columnar,
+    value::{LenientString, Value},
+    FloatPolicy, RawValue, SniffInfo,
+};
+
+pub use crate::{
+    empty_document, eq_documents, from_bytes, from_bytes_prefix, from_dyn_reader, from_raw, from_reader,
+    from_reader_mut, from_value, read_docs, serialized_size, sniff, to_bytes, to_bytes_tuned_cache, to_dyn_writer,
+    to_raw, to_value, to_writer, to_writer_mut, to_writer_tuned_cache, verify, verify_as, write_all_docs,
+};
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use crate::{from_file, from_file_or_default, to_file};
+
+#[cfg(feature = "bytes")]
+pub use crate::{from_bytes_buf, to_bytes_mut};
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub use crate::compress::Compression;
+
+#[cfg(feature = "zstd")]
+pub use crate::compress::ZstdDictionary;
+
+#[cfg(all(feature = "store", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub use crate::store::FileStore;
+
+#[cfg(feature = "net")]
+pub use crate::net::{
+    read_framed_from, write_framed_into, FrameCodec, FrameReader, IndexedFrameReader, IndexedFrameWriter,
+};
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// Time measurement for profiling.<br>
+/// `Instant` panics on wasm32-unknown-unknown, so time is not measured there
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) struct Timer(std::time::Instant);
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl Timer {
+    pub fn start() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) struct Timer;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+impl Timer {
+    pub fn start() -> Self {
+        Self
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Deserialization time and size of a single type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileEntry {
+    /// How many values of this type were deserialized
+    pub count: usize,
+
+    /// Total time spent deserializing values of this type, including nested values.<br>
+    /// Always zero on wasm32-unknown-unknown
+    pub time: Duration,
+
+    /// Total amount of bytes read for values of this type, including nested values
+    pub bytes: u64,
+}
+
+/// Per-type deserialization statistics, collected by a Deserializer with profiling enabled.<br>
+/// Types are recorded by names of structs, enums, newtype, tuple and unit structs passed to the deserializer
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    entries: HashMap<&'static str, ProfileEntry>,
+}
+
+impl Profile {
+    pub(crate) fn record(&mut self, name: &'static str, time: Duration, bytes: u64) {
+        let entry = self.entries.entry(name).or_default();
+        entry.count += 1;
+        entry.time += time;
+        entry.bytes += bytes;
+    }
+
+    /// Add entries recorded by another profile
+    pub(crate) fn merge(&mut self, other: &Profile) {
+        for (name, other) in &other.entries {
+            let entry = self.entries.entry(name).or_default();
+            entry.count += other.count;
+            entry.time += other.time;
+            entry.bytes += other.bytes;
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProfileEntry> {
+        self.entries.get(name)
+    }
+
+    /// Recorded entries, sorted by time spent, longest first
+    pub fn entries(&self) -> Vec<(&'static str, ProfileEntry)> {
+        let mut entries: Vec<_> = self.entries.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        entries
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, entry) in self.entries() {
+            writeln!(
+                f,
+                "{name}: {} values, {:?}, {} bytes",
+                entry.count, entry.time, entry.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
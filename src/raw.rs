@@ -1,16 +1,18 @@
 use std::{
-    fmt, io::{self, Read, Write}, marker::PhantomData, ops::Deref
+    fmt, io::{self, Read}, marker::PhantomData, ops::Deref, sync::Arc
 };
 
 use serde::{de::{DeserializeOwned, Visitor}, Deserialize, Serialize};
 
 use crate::{
-    de::{DeserializeError, Deserializer, ReadStrError, ReadTagError}, ser::SerializeError, tag::{FloatWidth, IntWidth, OptionTag, StrNewIndex, StructType, TagParameter, TypeTag}, varint, Serializer, FORMAT_VERSION
+    de::{DeserializeError, Deserializer, ReadTagError}, ser::SerializeError, tag::{FloatWidth, IntWidth, KeyType, OptionTag, StructType, TagParameter, TypeTag}, varint, io_util::copy_data, Serializer, FORMAT_VERSION
 };
 
 pub(crate) const RAW_VALUE_MAGIC_STRING: &str = "smoldata::RAW::ef812e7a46e822cd";
 
-/// Represents serialized object bytes
+/// Represents serialized object bytes.<br>
+/// Strings inside are stored relative to the value itself, not to the document it was read from,
+/// so it can be written into any other document, with its strings remapped into that document's string map
 pub struct RawValue(Box<[u8]>);
 
 enum RawValueSerStack {
@@ -22,6 +24,7 @@ enum RawValueSerStack {
         value_next: bool,
         remaining: Option<usize>,
         string_keys: bool,
+        key_type: Option<KeyType>,
     },
 }
 
@@ -50,6 +53,15 @@ impl RawValue {
     ) -> Result<Vec<u8>, DeserializeError> {
         let mut buf: Vec<u8> = vec![];
         let mut se = Serializer::new_bare(&mut buf, 256);
+        Self::copy_value(de, &mut se)?;
+        Ok(buf)
+    }
+
+    /// Copy a single value from the deserializer into the serializer, remapping its strings into the serializer's string map
+    pub(crate) fn copy_value<R: io::Read, W: io::Write>(
+        de: &mut Deserializer<R>,
+        se: &mut Serializer<W>,
+    ) -> Result<(), DeserializeError> {
         let mut stack: Vec<RawValueSerStack> = vec![];
         let mut first = true;
 
@@ -80,6 +92,7 @@ impl RawValue {
                         value_next,
                         remaining,
                         string_keys,
+                        key_type,
                     } => {
                         if !*value_next {
                             match remaining {
@@ -103,6 +116,20 @@ impl RawValue {
                             }
 
                             *value_next = true;
+
+                            match key_type {
+                                Some(KeyType::Primitive(tag)) => {
+                                    Self::copy_tag_data(de, se, *tag)?;
+                                    continue;
+                                }
+                                Some(KeyType::Tuple(tags)) => {
+                                    for tag in tags.iter() {
+                                        Self::copy_tag_data(de, se, *tag)?;
+                                    }
+                                    continue;
+                                }
+                                None => {}
+                            }
                         } else {
                             *value_next = false;
                         }
@@ -120,6 +147,10 @@ impl RawValue {
                         *s = news;
                     }
                     tag
+                })
+                .map_err(|e| match e {
+                    SerializeError::IOError(e) => DeserializeError::IOError(e),
+                    e => DeserializeError::Custom(e.to_string()),
                 })?;
             } else {
                 se.write_tag(tag)?;
@@ -157,8 +188,8 @@ impl RawValue {
                     se.writer.write_all(slice)?;
                 }
                 TypeTag::Str(_) => {}
-                TypeTag::StrDirect | TypeTag::Bytes => {
-                    let len = varint::read_unsigned_varint(&mut de.reader)?;
+                TypeTag::StrDirect | TypeTag::Bytes | TypeTag::ExternalBytes => {
+                    let len = varint::read_length(&mut de.reader)?;
                     varint::write_unsigned_varint(&mut se.writer, len)?;
                     copy_data::<1024, _, _>(&mut de.reader, &mut se.writer, len)?;
                 }
@@ -176,12 +207,13 @@ impl RawValue {
                     ty: StructType::Struct,
                     str: _,
                 } => {
-                    let len = varint::read_unsigned_varint(&mut de.reader)?;
+                    let len = varint::read_length(&mut de.reader)?;
                     varint::write_unsigned_varint(&mut se.writer, len)?;
                     if len > 0 {
                         stack.push(RawValueSerStack::Map {
                             remaining: Some(len),
                             string_keys: true,
+                            key_type: None,
                             value_next: false,
                         });
                     }
@@ -194,7 +226,7 @@ impl RawValue {
                     ty: StructType::Tuple,
                     str: _,
                 } => {
-                    let len = varint::read_unsigned_varint(&mut de.reader)?;
+                    let len = varint::read_length(&mut de.reader)?;
                     varint::write_unsigned_varint(&mut se.writer, len)?;
                     if len > 0 {
                         stack.push(RawValueSerStack::Seq {
@@ -218,7 +250,7 @@ impl RawValue {
                 }
                 TypeTag::Map { has_length } => {
                     let len = has_length
-                        .then(|| varint::read_unsigned_varint(&mut de.reader))
+                        .then(|| varint::read_length(&mut de.reader))
                         .transpose()?;
                     if let Some(len) = len {
                         varint::write_unsigned_varint(&mut se.writer, len)?;
@@ -227,6 +259,21 @@ impl RawValue {
                         stack.push(RawValueSerStack::Map {
                             remaining: len,
                             string_keys: false,
+                            key_type: None,
+                            value_next: false,
+                        });
+                    }
+                }
+                TypeTag::PrimitiveKeyMap => {
+                    let len = varint::read_length(&mut de.reader)?;
+                    varint::write_unsigned_varint(&mut se.writer, len)?;
+                    let key_type = de.read_key_type()?;
+                    se.write_key_type(&key_type)?;
+                    if len > 0 {
+                        stack.push(RawValueSerStack::Map {
+                            remaining: Some(len),
+                            string_keys: false,
+                            key_type: Some(key_type),
                             value_next: false,
                         });
                     }
@@ -235,78 +282,58 @@ impl RawValue {
             }
         }
 
-        Ok(buf)
+        Ok(())
     }
 
-    pub(crate) fn serialize_raw<W: io::Write>(data: &[u8], ser: &mut Serializer<W>) -> Result<(), SerializeError> {
+    /// Copy data following a tag, without the tag itself
+    pub(crate) fn copy_tag_data<R: io::Read, W: io::Write>(
+        de: &mut Deserializer<R>,
+        se: &mut Serializer<W>,
+        tag: TypeTag,
+    ) -> Result<(), DeserializeError> {
+        for param in tag.tag_params() {
+            match param {
+                TagParameter::Varint => varint::copy_varint(&mut de.reader, &mut se.writer)?,
+                TagParameter::FixedIntBytes(width) => {
+                    copy_data::<{ IntWidth::MAX_BYTES }, _, _>(&mut de.reader, &mut se.writer, width.bytes())?
+                }
+                TagParameter::VarintLengthPrefixedBytearray => {
+                    let len = varint::read_length(&mut de.reader)?;
+                    varint::write_unsigned_varint(&mut se.writer, len)?;
+                    copy_data::<1024, _, _>(&mut de.reader, &mut se.writer, len)?;
+                }
+            }
+        }
+        Ok(())
+    }
 
+    pub(crate) fn serialize_raw<W: io::Write>(data: &[u8], ser: &mut Serializer<W>) -> Result<(), SerializeError> {
         let mut de = Deserializer::new_bare(io::Cursor::new(data), FORMAT_VERSION);
 
         loop {
-            let tag = de.read_tag();
-
-            let tag = match tag {
-                Ok(tag) => tag,
+            match de.peek_tag() {
+                Ok(_) => {}
                 Err(ReadTagError::IOError(e)) if matches!(e.kind(), io::ErrorKind::UnexpectedEof) => {
                     break;
                 },
                 Err(ReadTagError::IOError(e)) => return Err(e.into()),
-                Err(ReadTagError::InvalidTag(i)) => return Err(RawValueReadingError::InvalidTag(i).into()),
-            };
-
-            let mut tag_args = tag.tag_params();
-            let mut write_tag = true;
-
-            if let Some(str_ty) = tag.get_str() {
-                let str = match de.read_str(str_ty) {
-                    Ok(s) => s,
-                    Err(ReadStrError::IOError(e)) => return Err(e.into()),
-                    Err(ReadStrError::InvalidStringId(i)) => return Err(RawValueReadingError::InvalidStringId(i).into()),
-                    Err(ReadStrError::InvalidUTF8String) => return Err(RawValueReadingError::InvalidUTF8String.into()),
-                    Err(ReadStrError::ReadVarint(e)) => return Err(RawValueReadingError::ReadVarint(e).into()),
-                };
-
-                ser.write_cached_str(str, &|s| {
-                    let mut tag = tag;
-                    if let Some(str) = tag.get_str_mut() {
-                        *str = s;
-                    };
-                    tag
-                })?;
-
-                write_tag = false;
-
-                let skip = match str_ty {
-                    StrNewIndex::New => 2,
-                    StrNewIndex::Index => 1,
-                };
-                tag_args = &tag_args[skip..];
-            }
-
-            if write_tag {
-                ser.write_tag(tag)?;
+                Err(ReadTagError::InvalidTag(i) | ReadTagError::ReservedTag(i)) => {
+                    return Err(RawValueReadingError::InvalidTag(i).into())
+                }
+                Err(ReadTagError::ReadVarint(e)) => return Err(RawValueReadingError::ReadVarint(e).into()),
+                Err(ReadTagError::TimedOut) => return Err(SerializeError::Custom(Box::new(DeserializeError::TimedOut))),
             }
 
-            for arg in tag_args {
-                match arg {
-                    TagParameter::FixedIntBytes(width) => {
-                        let mut buf = [0u8; IntWidth::MAX_BYTES];
-                        let buf = &mut buf[..width.bytes()];
-                        de.reader.read_exact(buf)?;
-                        ser.writer.write_all(buf)?;
-                    },
-                    TagParameter::Varint => {
-                        varint::copy_varint(&mut de.reader, &mut ser.writer)?;
-                    },
-                    TagParameter::VarintLengthPrefixedBytearray => {
-                        let len = match varint::read_unsigned_varint(&mut de.reader) {
-                            Ok(len) => len,
-                            Err(e) => return Err(RawValueReadingError::ReadVarint(e).into()),
-                        };
-                        copy_data::<1024, _, _>(&mut de.reader, &mut ser.writer, len)?;
-                    },
+            Self::copy_value(&mut de, ser).map_err(|e| match e {
+                DeserializeError::IOError(e) => e.into(),
+                DeserializeError::InvalidTag(i) | DeserializeError::ReservedTag(i) => {
+                    RawValueReadingError::InvalidTag(i).into()
                 }
-            }
+                DeserializeError::InvalidStringId(i) => RawValueReadingError::InvalidStringId(i).into(),
+                DeserializeError::InvalidUTF8String => RawValueReadingError::InvalidUTF8String.into(),
+                DeserializeError::ReadVarint(e) => RawValueReadingError::ReadVarint(e).into(),
+                e => SerializeError::Custom(Box::new(e)),
+            })?;
         }
 
         Ok(())
@@ -337,7 +364,24 @@ impl RawValue {
         T::deserialize(&mut self.create_deserializer())
     }
 
-    pub fn serialize_from<T: Serialize>(value: &T) -> Result<Self, SerializeError> {
+    /// Name of the enum variant this value holds, None if it's not an enum variant.<br>
+    /// Value is left as-is, so it can be routed by variant before being deserialized
+    pub fn peek_variant_name(&self) -> Result<Option<Arc<str>>, DeserializeError> {
+        let mut de = self.create_deserializer();
+        let TypeTag::EnumVariant { str, .. } = de.peek_tag()? else {
+            return Ok(None);
+        };
+        de.peek_tag_consume();
+        Ok(Some(de.read_str(str)?))
+    }
+
+    /// Write this value into a serializer, same as serializing it.<br>
+    /// Strings are interned into the serializer's string map, reusing strings already written into it
+    pub fn write_into<W: io::Write>(&self, ser: &mut Serializer<W>) -> Result<(), SerializeError> {
+        Self::serialize_raw(&self.0, ser)
+    }
+
+    pub fn serialize_from<T: ?Sized + Serialize>(value: &T) -> Result<Self, SerializeError> {
         let mut buf = vec![];
         let mut ser = Serializer::new_bare(&mut buf, 256);
         value.serialize(&mut ser)?;
@@ -571,6 +615,127 @@ impl<W: io::Write> serde::Serializer for RawValueSerializer<'_, W> {
 
 pub(crate) struct SerdeSerializerStub<Ok, Error: serde::ser::Error>(PhantomData<(Ok, Error)>);
 
+/// Implement `serde::Serializer` methods returning `Err($err)`, for serializers accepting only some kinds of values.<br>
+/// Methods are listed by the kind of value they serialize, `reject_serialize!(err; bool str seq struct)`
+macro_rules! reject_serialize {
+    ($err:expr; $($method:tt)*) => {
+        $($crate::raw::reject_serialize!(@method $err; $method);)*
+    };
+
+    (@method $err:expr; bool) => { fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; i8) => { fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; i16) => { fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; i32) => { fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; i64) => { fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; u8) => { fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; u16) => { fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; u32) => { fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; u64) => { fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; f32) => { fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; f64) => { fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; char) => { fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; str) => { fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; bytes) => { fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; none) => { fn serialize_none(self) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; some) => {
+        fn serialize_some<T: ?Sized + serde::Serialize>(self, _value: &T) -> Result<Self::Ok, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; unit) => { fn serialize_unit(self) -> Result<Self::Ok, Self::Error> { Err($err) } };
+    (@method $err:expr; unit_struct) => {
+        fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; unit_variant) => {
+        fn serialize_unit_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+        ) -> Result<Self::Ok, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; newtype_struct) => {
+        fn serialize_newtype_struct<T: ?Sized + serde::Serialize>(
+            self,
+            _name: &'static str,
+            _value: &T,
+        ) -> Result<Self::Ok, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; newtype_variant) => {
+        fn serialize_newtype_variant<T: ?Sized + serde::Serialize>(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _value: &T,
+        ) -> Result<Self::Ok, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; seq) => {
+        fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; tuple) => {
+        fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; tuple_struct) => {
+        fn serialize_tuple_struct(
+            self,
+            _name: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; tuple_variant) => {
+        fn serialize_tuple_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; map) => {
+        fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; struct) => {
+        fn serialize_struct(
+            self,
+            _name: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeStruct, Self::Error> {
+            Err($err)
+        }
+    };
+    (@method $err:expr; struct_variant) => {
+        fn serialize_struct_variant(
+            self,
+            _name: &'static str,
+            _variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Self::SerializeStructVariant, Self::Error> {
+            Err($err)
+        }
+    };
+}
+pub(crate) use reject_serialize;
+
 impl<Ok, Error: serde::ser::Error> serde::ser::SerializeTupleVariant for SerdeSerializerStub<Ok, Error> {
     type Ok = Ok;
 
@@ -688,29 +853,3 @@ impl<Ok, Error: serde::ser::Error> serde::ser::SerializeSeq for SerdeSerializerS
         panic!("stub called!")
     }
 }
-
-fn copy_data<const BUF_SIZE: usize, S: io::Read, D: io::Write>(
-    src: &mut S,
-    dst: &mut D,
-    mut amount: usize,
-) -> Result<(), io::Error> {
-    let mut buf = [0u8; BUF_SIZE];
-    while amount > 0 {
-        let size = amount.min(BUF_SIZE);
-        let slice = &mut buf[..size];
-
-        let read = src.read(slice)?;
-        if read == 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::UnexpectedEof,
-                "EOF while copying data",
-            ));
-        }
-        let slice = &slice[..read];
-        dst.write_all(slice)?;
-
-        amount -= read;
-    }
-
-    Ok(())
-}
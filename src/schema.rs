@@ -0,0 +1,566 @@
+use std::fmt;
+
+use serde::{de, ser, Deserialize, Serialize};
+
+use crate::ser::SerializeError;
+
+/// Structural shape of a value, captured by [`capture_shape`] without writing any data.<br>
+/// Sequences and maps hold the shape of their first element as a sample of the rest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shape {
+    Unit,
+    Bool,
+    Int { signed: bool, bits: u32 },
+    Float { bits: u32 },
+    Char,
+    Str,
+    Bytes,
+    Option(Option<Box<Shape>>),
+    Struct(&'static str, ShapeData),
+    Variant(&'static str, &'static str, ShapeData),
+    Seq { len: usize, sample: Option<Box<Shape>> },
+    Tuple(Vec<Shape>),
+    Map { len: usize, sample: Option<Box<(Shape, Shape)>> },
+
+    /// `RawValue`, its data is not inspected
+    Raw,
+}
+
+/// Data of a struct or enum variant shape
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShapeData {
+    Unit,
+    Newtype(Box<Shape>),
+    Tuple(Vec<Shape>),
+    Struct(Vec<(&'static str, Shape)>),
+}
+
+/// Capture the shape of a value as it would be serialized: types, field and variant names and lengths.<br>
+/// Nothing is written, so it's cheap to capture shapes of large values for documentation or schema export
+pub fn capture_shape<T: ?Sized + Serialize>(data: &T) -> Result<Shape, SerializeError> {
+    data.serialize(ShapeSerializer)
+}
+
+/// Field names of a struct, as listed by its `Deserialize` implementation, None if it doesn't read a struct.<br>
+/// For tooling reflecting over types, such as validators or form generators, that only needs names
+pub fn field_names<'de, T: Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    match T::deserialize(NameProbe) {
+        Err(ProbeResult::Fields(fields)) => Some(fields),
+        _ => None,
+    }
+}
+
+/// Variant names of an enum, as listed by its `Deserialize` implementation, None if it doesn't read an enum
+pub fn variant_names<'de, T: Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    match T::deserialize(NameProbe) {
+        Err(ProbeResult::Variants(variants)) => Some(variants),
+        _ => None,
+    }
+}
+
+/// Deserializer stopping at the first requested value, capturing names passed with it
+struct NameProbe;
+
+#[derive(Debug)]
+enum ProbeResult {
+    Fields(&'static [&'static str]),
+    Variants(&'static [&'static str]),
+    Other,
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("name probe finished")
+    }
+}
+
+impl std::error::Error for ProbeResult {}
+
+impl de::Error for ProbeResult {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        Self::Other
+    }
+}
+
+impl<'de> de::Deserializer<'de> for NameProbe {
+    type Error = ProbeResult;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, ProbeResult> {
+        Err(ProbeResult::Other)
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, ProbeResult> {
+        Err(ProbeResult::Fields(fields))
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, ProbeResult> {
+        Err(ProbeResult::Variants(variants))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map identifier ignored_any
+    }
+}
+
+struct ShapeSerializer;
+
+impl ser::Serializer for ShapeSerializer {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    type SerializeSeq = SeqShape;
+    type SerializeTuple = TupleShape;
+    type SerializeTupleStruct = TupleShape;
+    type SerializeTupleVariant = TupleShape;
+    type SerializeMap = MapShape;
+    type SerializeStruct = StructShape;
+    type SerializeStructVariant = StructShape;
+
+    fn serialize_bool(self, _: bool) -> Result<Shape, SerializeError> {
+        Ok(Shape::Bool)
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: true, bits: i8::BITS })
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: true, bits: i16::BITS })
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: true, bits: i32::BITS })
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: true, bits: i64::BITS })
+    }
+
+    fn serialize_i128(self, _: i128) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: true, bits: i128::BITS })
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: false, bits: u8::BITS })
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: false, bits: u16::BITS })
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: false, bits: u32::BITS })
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: false, bits: u64::BITS })
+    }
+
+    fn serialize_u128(self, _: u128) -> Result<Shape, SerializeError> {
+        Ok(Shape::Int { signed: false, bits: u128::BITS })
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Shape, SerializeError> {
+        Ok(Shape::Float { bits: 32 })
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Shape, SerializeError> {
+        Ok(Shape::Float { bits: 64 })
+    }
+
+    fn serialize_char(self, _: char) -> Result<Shape, SerializeError> {
+        Ok(Shape::Char)
+    }
+
+    fn serialize_str(self, _: &str) -> Result<Shape, SerializeError> {
+        Ok(Shape::Str)
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Shape, SerializeError> {
+        Ok(Shape::Bytes)
+    }
+
+    fn serialize_none(self) -> Result<Shape, SerializeError> {
+        Ok(Shape::Option(None))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Shape, SerializeError> {
+        Ok(Shape::Option(Some(Box::new(capture_shape(value)?))))
+    }
+
+    fn serialize_unit(self) -> Result<Shape, SerializeError> {
+        Ok(Shape::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Shape, SerializeError> {
+        Ok(Shape::Struct(name, ShapeData::Unit))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Shape, SerializeError> {
+        Ok(Shape::Variant(name, variant, ShapeData::Unit))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Shape, SerializeError> {
+        if name == crate::raw::RAW_VALUE_MAGIC_STRING {
+            return Ok(Shape::Raw);
+        }
+        Ok(Shape::Struct(name, ShapeData::Newtype(Box::new(capture_shape(value)?))))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Shape, SerializeError> {
+        Ok(Shape::Variant(name, variant, ShapeData::Newtype(Box::new(capture_shape(value)?))))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqShape, SerializeError> {
+        Ok(SeqShape { len: 0, sample: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<TupleShape, SerializeError> {
+        Ok(TupleShape::new(TupleKind::Tuple, len))
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<TupleShape, SerializeError> {
+        Ok(TupleShape::new(TupleKind::Struct(name), len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<TupleShape, SerializeError> {
+        Ok(TupleShape::new(TupleKind::Variant(name, variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapShape, SerializeError> {
+        Ok(MapShape {
+            len: 0,
+            key: None,
+            sample: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructShape, SerializeError> {
+        Ok(StructShape {
+            name,
+            variant: None,
+            fields: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructShape, SerializeError> {
+        Ok(StructShape {
+            name,
+            variant: Some(variant),
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+struct SeqShape {
+    len: usize,
+    sample: Option<Shape>,
+}
+
+impl ser::SerializeSeq for SeqShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        if self.sample.is_none() {
+            self.sample = Some(capture_shape(value)?);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(Shape::Seq {
+            len: self.len,
+            sample: self.sample.map(Box::new),
+        })
+    }
+}
+
+enum TupleKind {
+    Tuple,
+    Struct(&'static str),
+    Variant(&'static str, &'static str),
+}
+
+struct TupleShape {
+    kind: TupleKind,
+    elements: Vec<Shape>,
+}
+
+impl TupleShape {
+    fn new(kind: TupleKind, len: usize) -> Self {
+        Self {
+            kind,
+            elements: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.elements.push(capture_shape(value)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Shape {
+        match self.kind {
+            TupleKind::Tuple => Shape::Tuple(self.elements),
+            TupleKind::Struct(name) => Shape::Struct(name, ShapeData::Tuple(self.elements)),
+            TupleKind::Variant(name, variant) => Shape::Variant(name, variant, ShapeData::Tuple(self.elements)),
+        }
+    }
+}
+
+impl ser::SerializeTuple for TupleShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for TupleShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for TupleShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(self.finish())
+    }
+}
+
+struct MapShape {
+    len: usize,
+
+    /// Key of the first entry, until its value is captured
+    key: Option<Shape>,
+    sample: Option<(Shape, Shape)>,
+}
+
+impl ser::SerializeMap for MapShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SerializeError> {
+        if self.len == 0 {
+            self.key = Some(capture_shape(key)?);
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        if let Some(key) = self.key.take() {
+            self.sample = Some((key, capture_shape(value)?));
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(Shape::Map {
+            len: self.len,
+            sample: self.sample.map(Box::new),
+        })
+    }
+}
+
+struct StructShape {
+    name: &'static str,
+    variant: Option<&'static str>,
+    fields: Vec<(&'static str, Shape)>,
+}
+
+impl StructShape {
+    fn push<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerializeError> {
+        let shape = capture_shape(value).map_err(|e| e.in_field(key))?;
+        self.fields.push((key, shape));
+        Ok(())
+    }
+
+    fn finish(self) -> Shape {
+        let data = ShapeData::Struct(self.fields);
+        match self.variant {
+            Some(variant) => Shape::Variant(self.name, variant, data),
+            None => Shape::Struct(self.name, data),
+        }
+    }
+}
+
+impl ser::SerializeStruct for StructShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerializeError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for StructShape {
+    type Ok = Shape;
+    type Error = SerializeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerializeError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Shape, SerializeError> {
+        Ok(self.finish())
+    }
+}
+
+/// Shape as an indented tree, one value per line
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+impl Shape {
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            Shape::Unit => writeln!(f, "()"),
+            Shape::Bool => writeln!(f, "bool"),
+            Shape::Int { signed: true, bits } => writeln!(f, "i{bits}"),
+            Shape::Int { signed: false, bits } => writeln!(f, "u{bits}"),
+            Shape::Float { bits } => writeln!(f, "f{bits}"),
+            Shape::Char => writeln!(f, "char"),
+            Shape::Str => writeln!(f, "str"),
+            Shape::Bytes => writeln!(f, "bytes"),
+            Shape::Option(None) => writeln!(f, "None"),
+            Shape::Option(Some(shape)) => {
+                writeln!(f, "Some")?;
+                write_child(f, None, shape, depth)
+            }
+            Shape::Struct(name, data) => {
+                write!(f, "{name}")?;
+                data.write_tree(f, depth)
+            }
+            Shape::Variant(name, variant, data) => {
+                write!(f, "{name}::{variant}")?;
+                data.write_tree(f, depth)
+            }
+            Shape::Seq { len, sample } => {
+                writeln!(f, "seq ({len})")?;
+                match sample {
+                    Some(sample) => write_child(f, Some("[]"), sample, depth),
+                    None => Ok(()),
+                }
+            }
+            Shape::Tuple(elements) => {
+                writeln!(f, "tuple")?;
+                write_elements(f, elements, depth)
+            }
+            Shape::Map { len, sample } => {
+                writeln!(f, "map ({len})")?;
+                match sample.as_deref() {
+                    Some((key, value)) => {
+                        write_child(f, Some("key"), key, depth)?;
+                        write_child(f, Some("value"), value, depth)
+                    }
+                    None => Ok(()),
+                }
+            }
+            Shape::Raw => writeln!(f, "raw"),
+        }
+    }
+}
+
+impl ShapeData {
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            ShapeData::Unit => writeln!(f),
+            ShapeData::Newtype(shape) => {
+                writeln!(f)?;
+                write_child(f, None, shape, depth)
+            }
+            ShapeData::Tuple(elements) => {
+                writeln!(f)?;
+                write_elements(f, elements, depth)
+            }
+            ShapeData::Struct(fields) => {
+                writeln!(f)?;
+                fields
+                    .iter()
+                    .try_for_each(|(name, shape)| write_child(f, Some(name), shape, depth))
+            }
+        }
+    }
+}
+
+fn write_elements(f: &mut fmt::Formatter<'_>, elements: &[Shape], depth: usize) -> fmt::Result {
+    elements
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, shape)| write_child(f, Some(&format!("{i}")), shape, depth))
+}
+
+fn write_child(f: &mut fmt::Formatter<'_>, name: Option<&str>, shape: &Shape, depth: usize) -> fmt::Result {
+    write!(f, "{:indent$}", "", indent = (depth + 1) * 2)?;
+    if let Some(name) = name {
+        write!(f, "{name}: ")?;
+    }
+    shape.write_tree(f, depth + 1)
+}
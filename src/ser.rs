@@ -1,7 +1,9 @@
 use std::{collections::HashMap, error::Error, fmt::Display, io, sync::Arc, ops::Deref};
 
+use serde::Serialize;
+
 use crate::{
-    raw::RawValueReadingError, tag::{FlatTypeTag, FloatWidth, IntWidth, OptionTag, StrNewIndex, StructType, TypeTag}, varint, MaybeArcStr, FORMAT_VERSION, MAGIC_HEADER
+    raw::{reject_serialize, RawValue, RawValueReadingError, SerdeSerializerStub}, tag::{FlatTypeTag, FloatWidth, IntWidth, KeyType, OptionTag, StrNewIndex, StructType, TypeTag}, varint, FloatPolicy, MaybeArcStr, FORMAT_VERSION, MAGIC_HEADER
 };
 
 const SERIALIZER_DEBUG_PRINT: bool = false;
@@ -40,8 +42,53 @@ pub enum SerializeError {
     #[error("Error while reading a RawValue")]
     RawValueReading(#[from] RawValueReadingError),
 
+    #[error("Compact map keys must all be of the same integer or char type")]
+    MixedMapKeyTypes,
+
+    #[error("Serializer string map is full, no more names can be cached")]
+    StringMapFull,
+
+    #[error("Attempted to serialize non-finite float at \"{0}\"")]
+    NonFiniteFloat(String),
+
+    #[error("Attempted to serialize field \"{0}\" more than once")]
+    DuplicateField(&'static str),
+
+    #[error("Attempted to serialize map key more than once")]
+    DuplicateMapKey,
+
+    #[error("Field \"{0}\" can't be skipped in a compact struct")]
+    SkippedCompactField(&'static str),
+
     #[error(transparent)]
-    Custom(Box<dyn Error>),
+    Custom(Box<dyn Error + Send + Sync>),
+}
+
+impl SerializeError {
+    /// Prepend field name to the path of errors that contain one
+    pub(crate) fn in_field(self, field: &str) -> Self {
+        self.in_path(|| field.into())
+    }
+
+    /// Prepend sequence element index to the path of errors that contain one
+    pub(crate) fn in_index(self, index: usize) -> Self {
+        self.in_path(|| format!("[{index}]"))
+    }
+
+    /// Prepend map key to the path of errors that contain one
+    pub(crate) fn in_key<K: ?Sized + serde::Serialize>(self, key: &K) -> Self {
+        self.in_path(|| match crate::to_value(&key) {
+            Ok(key) => format!("[{key}]"),
+            Err(_) => "[..]".into(),
+        })
+    }
+
+    fn in_path(self, segment: impl FnOnce() -> String) -> Self {
+        match self {
+            Self::NonFiniteFloat(path) => Self::NonFiniteFloat(crate::prepend_path(&segment(), &path)),
+            e => e,
+        }
+    }
 }
 
 impl serde::ser::Error for SerializeError {
@@ -53,6 +100,21 @@ impl serde::ser::Error for SerializeError {
     }
 }
 
+/// Callback storing a byte array outside of the serialized data, returns a reference to it
+pub type BlobStore = dyn Fn(&[u8]) -> io::Result<String> + Send + Sync;
+
+/// Which strings are written into the string map, to be referenced by later occurrences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InternPolicy {
+    /// Intern field names, variant names and string values up to the max cached length
+    #[default]
+    All,
+
+    /// Intern only field and variant names, writing string values directly.<br>
+    /// Keeps ids of names small in documents with many unique string values, which would otherwise push names to multi-byte ids
+    NamesOnly,
+}
+
 pub struct Serializer<W: io::Write> {
     pub(crate) writer: W,
     pub(crate) string_map: HashMap<Arc<str>, u32>,
@@ -60,6 +122,12 @@ pub struct Serializer<W: io::Write> {
 
     next_map_index: u32,
     max_cache_str_len: usize,
+    intern_policy: InternPolicy,
+    float_policy: FloatPolicy,
+    sort_maps: bool,
+    compact_map_keys: bool,
+    compact_structs: bool,
+    blob_store: Option<(usize, Arc<BlobStore>)>,
 }
 
 impl<W: io::Write> Serializer<W> {
@@ -86,20 +154,115 @@ impl<W: io::Write> Serializer<W> {
 
             next_map_index: 0,
             max_cache_str_len,
+            intern_policy: InternPolicy::All,
+            float_policy: FloatPolicy::Preserve,
+            sort_maps: false,
+            compact_map_keys: false,
+            compact_structs: false,
+            blob_store: None,
         }
     }
 
+    /// Create a bare serializer with the same options, for serializing values separately
+    fn new_nested<N: io::Write>(&self, writer: N) -> Serializer<N> {
+        let mut ser = Serializer::new_bare(writer, self.max_cache_str_len);
+        ser.intern_policy = self.intern_policy;
+        ser.float_policy = self.float_policy;
+        ser.sort_maps = self.sort_maps;
+        ser.compact_map_keys = self.compact_map_keys;
+        ser.compact_structs = self.compact_structs;
+        ser.blob_store = self.blob_store.clone();
+        ser
+    }
+
+    /// Set how NaN and infinite float values are handled
+    pub fn with_float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Set which strings are interned
+    pub fn with_intern_policy(mut self, policy: InternPolicy) -> Self {
+        self.intern_policy = policy;
+        self
+    }
+
+    /// Forget all strings written so far, freeing memory used by them.<br>
+    /// Later strings are written anew, reusing ids of forgotten ones, so readers don't need to do anything.
+    /// Their string maps stop growing as well, ids are replaced instead of added
+    pub fn clear_string_map(&mut self) {
+        self.string_map = Default::default();
+        self.next_map_index = 0;
+    }
+
+    /// Strings that can't be added into a full string map are written without caching
+    fn is_uncached_when_full(&self, s: &str) -> bool {
+        self.next_map_index == u32::MAX && !self.string_map.contains_key(s)
+    }
+
+    /// Write map entries sorted by their serialized keys, making output deterministic for maps with unstable ordering, such as HashMap.<br>
+    /// Entries are buffered until the map ends. Maps with meaningful ordering, such as IndexMap, lose it
+    pub fn with_sorted_maps(mut self, sort: bool) -> Self {
+        self.sort_maps = sort;
+        self
+    }
+
+    /// Write maps with integer or char keys with the key type written once, followed by untagged keys.<br>
+    /// Keys that are tuples or tuple structs of integers and chars, such as coordinates, are written the same way.<br>
+    /// Only maps with known length are affected, all keys of such map must be of the same type
+    pub fn with_compact_map_keys(mut self, compact: bool) -> Self {
+        self.compact_map_keys = compact;
+        self
+    }
+
+    /// Write struct fields positionally without their names, as tuple structs.<br>
+    /// Saves writing field names at the cost of self-description, data is read by field order and breaks if fields are reordered.
+    /// Fields skipped with `skip_serializing_if` can't be written in this mode
+    pub fn with_compact_structs(mut self, compact: bool) -> Self {
+        self.compact_structs = compact;
+        self
+    }
+
+    /// Hand byte arrays longer than `threshold` bytes to `store`, writing only the returned reference.<br>
+    /// Such data can only be read by a Deserializer with a blob loader set
+    pub fn with_blob_store(
+        mut self,
+        threshold: usize,
+        store: impl Fn(&[u8]) -> io::Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.blob_store = Some((threshold, Arc::new(store)));
+        self
+    }
+
+    /// Serialize a value, same as `value.serialize(&mut serializer)`.<br>
+    /// Many values can be written one after another, strings of earlier values are referenced instead of written again
+    pub fn write_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        value.serialize(self)
+    }
+
     pub(crate) fn write_tag(&mut self, tag: impl Into<FlatTypeTag>) -> Result<(), io::Error> {
         let tag = tag.into();
         serializer_debugprintln!(self, "tag: {tag:?}");
         self.writer.write_all(&[tag.into()])
     }
 
+    /// Write key type of a primitive key map
+    pub(crate) fn write_key_type(&mut self, key: &KeyType) -> Result<(), io::Error> {
+        match key {
+            KeyType::Primitive(tag) => self.write_tag(*tag),
+            KeyType::Tuple(tags) => {
+                self.write_tag(TypeTag::Tuple)?;
+                varint::write_unsigned_varint(&mut self.writer, tags.len())?;
+                tags.iter().try_for_each(|tag| self.write_tag(*tag))
+            }
+        }
+    }
+
     pub(crate) fn write_cached_str<'a>(
         &mut self,
         s: impl Into<MaybeArcStr<'a>>,
         tagmaker: &dyn Fn(StrNewIndex) -> TypeTag,
-    ) -> Result<(), io::Error> {
+    ) -> Result<(), SerializeError> {
         let s = s.into();
         if let Some(index) = self.string_map.get(s.deref()).copied() {
             self.write_tag(tagmaker(StrNewIndex::Index))?;
@@ -107,6 +270,7 @@ impl<W: io::Write> Serializer<W> {
             varint::write_unsigned_varint(&mut self.writer, index)?;
         } else {
             let index = self.next_map_index;
+            let next_index = index.checked_add(1).ok_or(SerializeError::StringMapFull)?;
 
             self.write_tag(tagmaker(StrNewIndex::New))?;
             varint::write_unsigned_varint(&mut self.writer, index)?;
@@ -115,13 +279,120 @@ impl<W: io::Write> Serializer<W> {
 
             serializer_debugprintln!(self, "string: {index} (\"{}\")", s.deref());
 
-            self.next_map_index += 1;
+            self.next_map_index = next_index;
             self.string_map.insert(s.into(), index);
         }
         Ok(())
     }
 }
 
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+impl<W: io::Write> Serializer<crate::compress::CompressedWriter<W>> {
+    /// Construct a new Serializer compressing written data.<br>
+    /// Only the header is left uncompressed, `Deserializer::new` decompresses data transparently
+    pub fn new_compressed(
+        mut writer: W,
+        max_cache_str_len: usize,
+        compression: crate::compress::Compression,
+    ) -> Result<Self, io::Error> {
+        writer.write_all(MAGIC_HEADER)?;
+        writer.write_all(&[FORMAT_VERSION | crate::HEADER_FLAGS_BIT, compression.id()])?;
+
+        let writer = crate::compress::CompressedWriter::new(writer, compression)?;
+        Ok(Self::new_bare(writer, max_cache_str_len))
+    }
+
+    /// Construct a new Serializer compressing written data with zstd and an external dictionary, whose id is written into the header.<br>
+    /// Read with `Deserializer::new_with_dictionaries` given the same dictionary
+    #[cfg(feature = "zstd")]
+    pub fn new_compressed_with_dictionary(
+        mut writer: W,
+        max_cache_str_len: usize,
+        level: i32,
+        dictionary: &crate::compress::ZstdDictionary,
+    ) -> Result<Self, io::Error> {
+        writer.write_all(MAGIC_HEADER)?;
+        writer.write_all(&[FORMAT_VERSION | crate::HEADER_FLAGS_BIT, crate::COMPRESSION_ZSTD_DICTIONARY])?;
+        writer.write_all(&dictionary.id().to_le_bytes())?;
+
+        let writer = crate::compress::CompressedWriter::with_zstd_dictionary(writer, level, dictionary)?;
+        Ok(Self::new_bare(writer, max_cache_str_len))
+    }
+
+    /// Finish compression, returning the inner writer.<br>
+    /// Compression is also finished when the serializer is dropped, but errors are ignored then
+    pub fn finish(self) -> Result<W, io::Error> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(feature = "checksum")]
+impl<W: io::Write> Serializer<crate::checksum::ChecksumWriter<W>> {
+    /// Construct a new Serializer writing a checksum of written data after it.<br>
+    /// Checksum is verified by `from_reader`, `from_bytes` and `Deserializer::verify_checksum`, so corrupted data
+    /// is reported as such instead of as an error somewhere inside it. Cannot be combined with compression
+    pub fn new_checksummed(mut writer: W, max_cache_str_len: usize) -> Result<Self, io::Error> {
+        writer.write_all(MAGIC_HEADER)?;
+        writer.write_all(&[FORMAT_VERSION | crate::HEADER_FLAGS_BIT, crate::HEADER_CHECKSUM_FLAG])?;
+
+        let writer = crate::checksum::ChecksumWriter::new(writer);
+        Ok(Self::new_bare(writer, max_cache_str_len))
+    }
+
+    /// Write the checksum, returning the inner writer.<br>
+    /// Checksum is also written when the serializer is dropped, but errors are ignored then
+    pub fn finish(self) -> Result<W, io::Error> {
+        self.writer.finish()
+    }
+}
+
+/// Writer of values placed one after another after a single header, such as records of an append-only log.<br>
+/// Strings are shared between all values, unlike writing each value with `to_writer_mut`. Read back with `StreamReader`.<br>
+/// Every distinct string stays in memory at both ends, so long streams of unique strings should use `InternPolicy::NamesOnly`
+/// or call [`Self::clear_string_map`] from time to time
+pub struct StreamWriter<W: io::Write> {
+    ser: Serializer<W>,
+    written: usize,
+}
+
+impl<W: io::Write> StreamWriter<W> {
+    /// Construct a new StreamWriter, writing the header.<br>
+    /// Writer preferred to be buffered
+    pub fn new(writer: W) -> Result<Self, io::Error> {
+        Ok(Self::from_serializer(Serializer::new(writer, 255)?))
+    }
+
+    /// Write values with a configured serializer, which must not have written anything but the header
+    pub fn from_serializer(ser: Serializer<W>) -> Self {
+        Self { ser, written: 0 }
+    }
+
+    /// Serialize a value as the next record
+    pub fn write<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerializeError> {
+        self.ser.write_value(value)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Amount of values written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Forget all strings written so far, see [`Serializer::clear_string_map`]
+    pub fn clear_string_map(&mut self) {
+        self.ser.clear_string_map();
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.ser.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.ser.writer
+    }
+}
+
 impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     type Ok = ();
 
@@ -208,6 +479,7 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
         Ok(())
     }
 
+    #[cfg(feature = "int128")]
     fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
         let varint = is_varint_better(v.unsigned_abs().leading_zeros(), 16, true);
         self.write_tag(TypeTag::Integer {
@@ -285,6 +557,7 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
         Ok(())
     }
 
+    #[cfg(feature = "int128")]
     fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
         let varint = is_varint_better(v.leading_zeros(), 16, false);
         self.write_tag(TypeTag::Integer {
@@ -302,6 +575,10 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        let v = self
+            .float_policy
+            .apply_f32(v)
+            .ok_or_else(|| SerializeError::NonFiniteFloat(String::new()))?;
         self.write_tag(TypeTag::Float(FloatWidth::F32))?;
         self.writer.write_all(&v.to_le_bytes())?;
 
@@ -311,6 +588,10 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        let v = self
+            .float_policy
+            .apply_f64(v)
+            .ok_or_else(|| SerializeError::NonFiniteFloat(String::new()))?;
         self.write_tag(TypeTag::Float(FloatWidth::F64))?;
         self.writer.write_all(&v.to_le_bytes())?;
 
@@ -338,7 +619,10 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
         if v.is_empty() {
             self.write_tag(TypeTag::EmptyStr)?;
-        } else if v.len() > self.max_cache_str_len {
+        } else if v.len() > self.max_cache_str_len
+            || self.intern_policy == InternPolicy::NamesOnly
+            || self.is_uncached_when_full(v)
+        {
             self.write_tag(TypeTag::StrDirect)?;
             varint::write_unsigned_varint(&mut self.writer, v.len())?;
             self.writer.write_all(v.as_bytes())?;
@@ -351,6 +635,19 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        if let Some((threshold, store)) = &self.blob_store {
+            if v.len() > *threshold {
+                let reference = store(v)?;
+                self.write_tag(TypeTag::ExternalBytes)?;
+                varint::write_unsigned_varint(&mut self.writer, reference.len())?;
+                self.writer.write_all(reference.as_bytes())?;
+
+                serializer_debugprintln!(self, "external bytes: {reference:?}");
+
+                return Ok(());
+            }
+        }
+
         self.write_tag(TypeTag::Bytes)?;
         varint::write_unsigned_varint(&mut self.writer, v.len())?;
         self.writer.write_all(v)?;
@@ -450,6 +747,7 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
             level: self.level,
             ser: self,
             remaining: len,
+            index: 0,
         })
     }
 
@@ -462,6 +760,7 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
             level: self.level,
             ser: self,
             remaining: len,
+            index: 0,
         })
     }
 
@@ -503,20 +802,31 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        self.write_tag(TypeTag::Map {
-            has_length: len.is_some(),
-        })?;
-        if let Some(len) = len {
-            serializer_debugprintln!(self, "len: {len}");
-            varint::write_unsigned_varint(&mut self.writer, len)?;
-        }
+        let keys = match len {
+            Some(len) if len > 0 && self.compact_map_keys => MapKeys::Pending(len),
+            _ => {
+                self.write_tag(TypeTag::Map {
+                    has_length: len.is_some(),
+                })?;
+                if let Some(len) = len {
+                    serializer_debugprintln!(self, "len: {len}");
+                    varint::write_unsigned_varint(&mut self.writer, len)?;
+                }
+                MapKeys::Tagged
+            }
+        };
 
         self.level += 1;
         Ok(SerializeMap {
             level: self.level,
+            sorted_entries: self.sort_maps.then(Vec::new),
             ser: self,
             remaining: len,
             value_next: false,
+            keys,
+            key_buf: vec![],
+            #[cfg(feature = "debug-checks")]
+            written_keys: Default::default(),
         })
     }
 
@@ -525,15 +835,22 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        self.write_tag(TypeTag::Struct(StructType::Struct))?;
+        let ty = match self.compact_structs {
+            true => StructType::Tuple,
+            false => StructType::Struct,
+        };
+        self.write_tag(TypeTag::Struct(ty))?;
         varint::write_unsigned_varint(&mut self.writer, len)?;
         serializer_debugprintln!(self, "len: {len}");
 
         self.level += 1;
         Ok(SerializeStruct {
             level: self.level,
+            compact: self.compact_structs,
             ser: self,
             remaining: len,
+            #[cfg(feature = "debug-checks")]
+            fields: Vec::with_capacity(len),
         })
     }
 
@@ -544,18 +861,22 @@ impl<'a, W: io::Write> serde::Serializer for &'a mut Serializer<W> {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        self.write_cached_str(variant, &|str| TypeTag::EnumVariant {
-            ty: StructType::Struct,
-            str,
-        })?;
+        let ty = match self.compact_structs {
+            true => StructType::Tuple,
+            false => StructType::Struct,
+        };
+        self.write_cached_str(variant, &|str| TypeTag::EnumVariant { ty, str })?;
         varint::write_unsigned_varint(&mut self.writer, len)?;
         serializer_debugprintln!(self, "len: {len}");
 
         self.level += 1;
         Ok(SerializeStructVariant {
             level: self.level,
+            compact: self.compact_structs,
             ser: self,
             remaining: len,
+            #[cfg(feature = "debug-checks")]
+            fields: Vec::with_capacity(len),
         })
     }
 
@@ -568,6 +889,7 @@ pub struct SerializeSeq<'a, W: io::Write> {
     ser: &'a mut Serializer<W>,
     remaining: Option<usize>,
     level: usize,
+    index: usize,
 }
 
 impl<W: io::Write> serde::ser::SerializeSeq for SerializeSeq<'_, W> {
@@ -590,7 +912,10 @@ impl<W: io::Write> serde::ser::SerializeSeq for SerializeSeq<'_, W> {
             *rem -= 1;
         }
 
-        value.serialize(&mut *self.ser)?;
+        value
+            .serialize(&mut *self.ser)
+            .map_err(|e| e.in_index(self.index))?;
+        self.index += 1;
 
         Ok(())
     }
@@ -613,6 +938,7 @@ pub struct SerializeTuple<'a, W: io::Write> {
     ser: &'a mut Serializer<W>,
     remaining: usize,
     level: usize,
+    index: usize,
 }
 
 impl<W: io::Write> serde::ser::SerializeTuple for SerializeTuple<'_, W> {
@@ -634,7 +960,10 @@ impl<W: io::Write> serde::ser::SerializeTuple for SerializeTuple<'_, W> {
 
         self.remaining -= 1;
 
-        value.serialize(&mut *self.ser)?;
+        value
+            .serialize(&mut *self.ser)
+            .map_err(|e| e.in_index(self.index))?;
+        self.index += 1;
 
         Ok(())
     }
@@ -738,6 +1067,64 @@ pub struct SerializeMap<'a, W: io::Write> {
     level: usize,
 
     value_next: bool,
+
+    /// Buffered key and value data, if map entries are sorted
+    sorted_entries: Option<Vec<(Vec<u8>, Vec<u8>)>>,
+
+    keys: MapKeys,
+
+    /// Untagged data of the last primitive key
+    key_buf: Vec<u8>,
+
+    /// Data of every written key
+    #[cfg(feature = "debug-checks")]
+    written_keys: std::collections::HashSet<Vec<u8>>,
+}
+
+enum MapKeys {
+    /// Keys are written as regular objects
+    Tagged,
+
+    /// Map header with this length is not written yet, waiting for the first key to decide on the key encoding
+    Pending(usize),
+
+    /// Keys are written as untagged data of this type
+    Primitive(KeyType),
+}
+
+impl<W: io::Write> SerializeMap<'_, W> {
+    /// Serialize a key into `key_buf`, writing the map header if this is the first key
+    fn serialize_primitive_key<T>(&mut self, key: &T) -> Result<(), SerializeError>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        self.key_buf.clear();
+        let tag = key.serialize(PrimitiveKeySerializer {
+            buf: &mut self.key_buf,
+        });
+
+        match (&self.keys, tag) {
+            (MapKeys::Primitive(expected), Ok(tag)) if *expected == tag => Ok(()),
+            (MapKeys::Primitive(_), Ok(_)) => Err(SerializeError::MixedMapKeyTypes),
+            (MapKeys::Primitive(_), Err(e)) => Err(e),
+            (MapKeys::Pending(len), Ok(tag)) => {
+                let len = *len;
+                self.ser.write_tag(TypeTag::PrimitiveKeyMap)?;
+                varint::write_unsigned_varint(&mut self.ser.writer, len)?;
+                self.ser.write_key_type(&tag)?;
+                self.keys = MapKeys::Primitive(tag);
+                Ok(())
+            }
+            (MapKeys::Pending(len), Err(_)) => {
+                let len = *len;
+                self.ser.write_tag(TypeTag::Map { has_length: true })?;
+                varint::write_unsigned_varint(&mut self.ser.writer, len)?;
+                self.keys = MapKeys::Tagged;
+                Ok(())
+            }
+            (MapKeys::Tagged, _) => Ok(()),
+        }
+    }
 }
 
 impl<W: io::Write> serde::ser::SerializeMap for SerializeMap<'_, W> {
@@ -766,6 +1153,39 @@ impl<W: io::Write> serde::ser::SerializeMap for SerializeMap<'_, W> {
 
         self.value_next = true;
 
+        if !matches!(self.keys, MapKeys::Tagged) {
+            self.serialize_primitive_key(key)?;
+        }
+
+        if let MapKeys::Primitive(_) = self.keys {
+            #[cfg(feature = "debug-checks")]
+            check_unique_key(&mut self.written_keys, &self.key_buf)?;
+
+            match &mut self.sorted_entries {
+                Some(entries) => entries.push((self.key_buf.clone(), vec![])),
+                None => self.ser.writer.write_all(&self.key_buf)?,
+            }
+            return Ok(());
+        }
+
+        if let Some(entries) = &mut self.sorted_entries {
+            let mut buf = vec![];
+            key.serialize(&mut self.ser.new_nested(&mut buf))?;
+
+            #[cfg(feature = "debug-checks")]
+            check_unique_key(&mut self.written_keys, &buf)?;
+
+            entries.push((buf, vec![]));
+            return Ok(());
+        }
+
+        #[cfg(feature = "debug-checks")]
+        {
+            let mut buf = vec![];
+            key.serialize(&mut self.ser.new_nested(&mut buf))?;
+            check_unique_key(&mut self.written_keys, &buf)?;
+        }
+
         key.serialize(&mut *self.ser)?;
 
         Ok(())
@@ -785,15 +1205,41 @@ impl<W: io::Write> serde::ser::SerializeMap for SerializeMap<'_, W> {
 
         self.value_next = false;
 
+        if let Some(entries) = &mut self.sorted_entries {
+            if let Some((_, buf)) = entries.last_mut() {
+                value.serialize(&mut self.ser.new_nested(buf))?;
+            }
+            return Ok(());
+        }
+
         value.serialize(&mut *self.ser)?;
 
         Ok(())
     }
 
+    fn serialize_entry<K, V>(&mut self, key: &K, value: &V) -> Result<(), Self::Error>
+    where
+        K: ?Sized + serde::Serialize,
+        V: ?Sized + serde::Serialize,
+    {
+        self.serialize_key(key)?;
+        self.serialize_value(value).map_err(|e| e.in_key(key))
+    }
+
     fn end(self) -> Result<Self::Ok, Self::Error> {
         if self.remaining.is_some_and(|rem| rem != 0) {
             return Err(SerializeError::LessElementsThanPromised);
         }
+        if let Some(mut entries) = self.sorted_entries {
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, value) in entries {
+                match self.keys {
+                    MapKeys::Primitive(_) => self.ser.writer.write_all(&key)?,
+                    _ => RawValue::serialize_raw(&key, self.ser)?,
+                }
+                RawValue::serialize_raw(&value, self.ser)?;
+            }
+        }
         if self.remaining.is_none() {
             self.ser.write_tag(TypeTag::End)?;
         }
@@ -808,6 +1254,9 @@ pub struct SerializeStruct<'a, W: io::Write> {
     ser: &'a mut Serializer<W>,
     remaining: usize,
     level: usize,
+    compact: bool,
+    #[cfg(feature = "debug-checks")]
+    fields: Vec<&'static str>,
 }
 
 impl<W: io::Write> serde::ser::SerializeStruct for SerializeStruct<'_, W> {
@@ -829,12 +1278,26 @@ impl<W: io::Write> serde::ser::SerializeStruct for SerializeStruct<'_, W> {
 
         self.remaining -= 1;
 
-        self.ser.write_cached_str(key, &TypeTag::Str)?;
-        value.serialize(&mut *self.ser)?;
+        #[cfg(feature = "debug-checks")]
+        check_unique_field(&mut self.fields, key)?;
+
+        if !self.compact {
+            self.ser.write_cached_str(key, &TypeTag::Str)?;
+        }
+        value
+            .serialize(&mut *self.ser)
+            .map_err(|e| e.in_field(key))?;
 
         Ok(())
     }
 
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        match self.compact {
+            true => Err(SerializeError::SkippedCompactField(key)),
+            false => Ok(()),
+        }
+    }
+
     fn end(self) -> Result<Self::Ok, Self::Error> {
         if self.remaining != 0 {
             return Err(SerializeError::LessElementsThanPromised);
@@ -850,6 +1313,9 @@ pub struct SerializeStructVariant<'a, W: io::Write> {
     ser: &'a mut Serializer<W>,
     remaining: usize,
     level: usize,
+    compact: bool,
+    #[cfg(feature = "debug-checks")]
+    fields: Vec<&'static str>,
 }
 
 impl<W: io::Write> serde::ser::SerializeStructVariant for SerializeStructVariant<'_, W> {
@@ -871,12 +1337,26 @@ impl<W: io::Write> serde::ser::SerializeStructVariant for SerializeStructVariant
 
         self.remaining -= 1;
 
-        self.ser.write_cached_str(key, &TypeTag::Str)?;
-        value.serialize(&mut *self.ser)?;
+        #[cfg(feature = "debug-checks")]
+        check_unique_field(&mut self.fields, key)?;
+
+        if !self.compact {
+            self.ser.write_cached_str(key, &TypeTag::Str)?;
+        }
+        value
+            .serialize(&mut *self.ser)
+            .map_err(|e| e.in_field(key))?;
 
         Ok(())
     }
 
+    fn skip_field(&mut self, key: &'static str) -> Result<(), Self::Error> {
+        match self.compact {
+            true => Err(SerializeError::SkippedCompactField(key)),
+            false => Ok(()),
+        }
+    }
+
     fn end(self) -> Result<Self::Ok, Self::Error> {
         if self.remaining != 0 {
             return Err(SerializeError::LessElementsThanPromised);
@@ -888,6 +1368,186 @@ impl<W: io::Write> serde::ser::SerializeStructVariant for SerializeStructVariant
     }
 }
 
+/// Error if a field was already written into the struct
+#[cfg(feature = "debug-checks")]
+fn check_unique_field(fields: &mut Vec<&'static str>, key: &'static str) -> Result<(), SerializeError> {
+    if fields.contains(&key) {
+        return Err(SerializeError::DuplicateField(key));
+    }
+    fields.push(key);
+    Ok(())
+}
+
+/// Error if a key with the same data was already written into the map.<br>
+/// Keys are compared by data written on their own, so interned strings are compared by content
+#[cfg(feature = "debug-checks")]
+fn check_unique_key(keys: &mut std::collections::HashSet<Vec<u8>>, key: &[u8]) -> Result<(), SerializeError> {
+    if !keys.insert(key.to_vec()) {
+        return Err(SerializeError::DuplicateMapKey);
+    }
+    Ok(())
+}
+
+/// Serializer of compact map keys, writes untagged integer and char data into `buf` and returns its type.<br>
+/// Integers wider than 8 bits and chars are always varint encoded, so all keys of one type share the same tag
+struct PrimitiveKeySerializer<'a> {
+    buf: &'a mut Vec<u8>,
+}
+
+impl PrimitiveKeySerializer<'_> {
+    fn write_unsigned<I: varint::UnsignedInt>(self, width: IntWidth, v: I) -> Result<KeyType, SerializeError> {
+        varint::write_unsigned_varint(&mut *self.buf, v)?;
+        Ok(KeyType::Primitive(TypeTag::Integer {
+            width,
+            signed: false,
+            varint: true,
+        }))
+    }
+
+    fn write_signed<I: varint::SignedInt>(self, width: IntWidth, v: I) -> Result<KeyType, SerializeError> {
+        varint::write_signed_varint(&mut *self.buf, v)?;
+        Ok(KeyType::Primitive(TypeTag::Integer {
+            width,
+            signed: true,
+            varint: true,
+        }))
+    }
+}
+
+impl<'a> serde::Serializer for PrimitiveKeySerializer<'a> {
+    type Ok = KeyType;
+    type Error = SerializeError;
+
+    type SerializeSeq = SerdeSerializerStub<KeyType, SerializeError>;
+    type SerializeTuple = TupleKeySerializer<'a>;
+    type SerializeTupleStruct = TupleKeySerializer<'a>;
+    type SerializeTupleVariant = SerdeSerializerStub<KeyType, SerializeError>;
+    type SerializeMap = SerdeSerializerStub<KeyType, SerializeError>;
+    type SerializeStruct = SerdeSerializerStub<KeyType, SerializeError>;
+    type SerializeStructVariant = SerdeSerializerStub<KeyType, SerializeError>;
+
+    reject_serialize!(SerializeError::MixedMapKeyTypes;
+        bool f32 f64 str bytes none some unit unit_struct unit_variant newtype_struct newtype_variant
+        seq tuple_variant map struct struct_variant
+    );
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.buf.push(v as u8);
+        Ok(KeyType::Primitive(TypeTag::Integer {
+            width: IntWidth::W8,
+            signed: true,
+            varint: false,
+        }))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.write_signed(IntWidth::W16, v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.write_signed(IntWidth::W32, v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.write_signed(IntWidth::W64, v)
+    }
+
+    #[cfg(feature = "int128")]
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.write_signed(IntWidth::W128, v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.buf.push(v);
+        Ok(KeyType::Primitive(TypeTag::Integer {
+            width: IntWidth::W8,
+            signed: false,
+            varint: false,
+        }))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.write_unsigned(IntWidth::W16, v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.write_unsigned(IntWidth::W32, v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.write_unsigned(IntWidth::W64, v)
+    }
+
+    #[cfg(feature = "int128")]
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.write_unsigned(IntWidth::W128, v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        varint::write_unsigned_varint(&mut *self.buf, v as u32)?;
+        Ok(KeyType::Primitive(TypeTag::Char { varint: true }))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(TupleKeySerializer {
+            buf: self.buf,
+            tags: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_tuple(len)
+    }
+}
+
+/// Serializer of tuple compact map keys, writes untagged data of each element and collects their tags
+struct TupleKeySerializer<'a> {
+    buf: &'a mut Vec<u8>,
+    tags: Vec<TypeTag>,
+}
+
+impl serde::ser::SerializeTuple for TupleKeySerializer<'_> {
+    type Ok = KeyType;
+    type Error = SerializeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        match value.serialize(PrimitiveKeySerializer { buf: self.buf })? {
+            KeyType::Primitive(tag) => {
+                self.tags.push(tag);
+                Ok(())
+            }
+            KeyType::Tuple(_) => Err(SerializeError::MixedMapKeyTypes),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(KeyType::Tuple(self.tags.into_boxed_slice()))
+    }
+}
+
+impl serde::ser::SerializeTupleStruct for TupleKeySerializer<'_> {
+    type Ok = KeyType;
+    type Error = SerializeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        serde::ser::SerializeTuple::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        serde::ser::SerializeTuple::end(self)
+    }
+}
+
 fn is_varint_better(abs_leading_zeros: u32, bytewidth: u32, signed: bool) -> bool {
     let value_width = bytewidth * 8 - abs_leading_zeros;
 
@@ -907,6 +1567,21 @@ mod test {
     #[allow(unused_imports)]
     use super::*;
 
+    #[test]
+    fn test_full_string_map() {
+        use serde::Serialize;
+
+        let mut vec = vec![];
+        let mut ser = Serializer::new_bare(&mut vec, 256);
+        ser.next_map_index = u32::MAX;
+
+        "value".serialize(&mut ser).unwrap();
+        assert_eq!(ser.writer[0], u8::from(FlatTypeTag::StrDirect));
+
+        let res = serde::Serializer::serialize_unit_variant(&mut ser, "Enum", 0, "Variant");
+        assert!(matches!(res, Err(SerializeError::StringMapFull)));
+    }
+
     #[test]
     fn test_is_varint_better() {
         let varint_short_values = [0x0u16, 0x7f, 0x0f];
@@ -0,0 +1,195 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{de::DeserializeError, ser::SerializeError, value::Value};
+
+/// Conversion of documents that can't be read as the stored type, see [`FileStore::with_migration`]
+pub type Migration<T> = dyn Fn(Value) -> Result<T, DeserializeError> + Send + Sync;
+
+/// Document saved in a file, such as a save file or config.<br>
+/// Saves are atomic, data is written into a temporary file next to the document which then replaces it,
+/// so a crash while saving leaves the previous document intact. Previous documents can be kept as numbered backups
+pub struct FileStore<T> {
+    path: PathBuf,
+    backups: usize,
+    migration: Option<Box<Migration<T>>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> FileStore<T> {
+    /// Store of a document at `path`, keeping no backups
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            backups: 0,
+            migration: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set how many previous documents to keep.<br>
+    /// Backups are stored next to the document, with `.bak1` being the newest
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Set a conversion of documents that can't be read as `T`, such as ones saved by an older version of the program.<br>
+    /// Such documents are read as a [`Value`] and passed to `migrate`, the migrated document replaces the old one on the next save,
+    /// with the old one kept in backups
+    pub fn with_migration(
+        mut self,
+        migrate: impl Fn(Value) -> Result<T, DeserializeError> + Send + Sync + 'static,
+    ) -> Self {
+        self.migration = Some(Box::new(migrate));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of a backup, starting from 1 for the newest one
+    pub fn backup_path(&self, index: usize) -> PathBuf {
+        self.with_extension(&format!("bak{index}"))
+    }
+
+    fn with_extension(&self, ext: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(ext);
+        self.path.with_file_name(name)
+    }
+
+    /// Shift backups by one, copying the current document into the newest backup.<br>
+    /// Document is copied instead of moved, so it exists at all times
+    fn rotate_backups(&self) -> io::Result<()> {
+        if self.backups == 0 {
+            return Ok(());
+        }
+
+        for index in (1..self.backups).rev() {
+            ignore_not_found(fs::rename(self.backup_path(index), self.backup_path(index + 1)))?;
+        }
+        ignore_not_found(fs::copy(&self.path, self.backup_path(1)).map(|_| ()))
+    }
+
+    /// Create a new temporary file next to the document.<br>
+    /// Names are unique, so concurrent saves, even from different processes, don't write into the same file
+    fn create_temp(&self) -> io::Result<(PathBuf, File)> {
+        static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+        loop {
+            let index = NEXT_TEMP.fetch_add(1, Ordering::Relaxed);
+            let path = self.with_extension(&format!("{}-{index}.tmp", std::process::id()));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((path, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned> FileStore<T> {
+    /// Read the document, migrating it if it can't be read as `T` and a migration is set
+    pub fn load(&self) -> Result<T, DeserializeError> {
+        self.read(&self.path)
+    }
+
+    /// Read the document, returning the default value if it doesn't exist yet
+    pub fn load_or_default(&self) -> Result<T, DeserializeError>
+    where
+        T: Default,
+    {
+        match self.read(&self.path) {
+            Err(DeserializeError::IOError(e)) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+            res => res,
+        }
+    }
+
+    /// Read the document, falling back to the newest readable backup if it's missing or corrupted.<br>
+    /// Returns the error of reading the document if no backup could be read either
+    pub fn load_or_backup(&self) -> Result<T, DeserializeError> {
+        let err = match self.load() {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+
+        (1..=self.backups)
+            .find_map(|index| self.read(&self.backup_path(index)).ok())
+            .ok_or(err)
+    }
+
+    /// Read a document or a backup, migrating it if needed.<br>
+    /// Returns the error of reading it as `T` if it can't be read as a `Value` either
+    fn read(&self, path: &Path) -> Result<T, DeserializeError> {
+        let err = match crate::from_file(path) {
+            Ok(data) => return Ok(data),
+            Err(e) => e,
+        };
+
+        let Some(migrate) = &self.migration else {
+            return Err(err);
+        };
+        match crate::from_file::<Value>(path) {
+            Ok(value) => migrate(value),
+            Err(_) => Err(err),
+        }
+    }
+}
+
+impl<T: Serialize> FileStore<T> {
+    /// Atomically replace the document, moving the previous one into backups
+    pub fn save(&self, data: &T) -> Result<(), SerializeError> {
+        let (temp, file) = self.create_temp()?;
+
+        let mut writer = io::BufWriter::new(file);
+        let res = crate::to_writer(data, &mut writer)
+            .and_then(|_| Ok(writer.flush()?))
+            .and_then(|_| Ok(writer.get_ref().sync_all()?));
+        drop(writer);
+
+        let res = res
+            .and_then(|_| Ok(self.rotate_backups()?))
+            .and_then(|_| Ok(fs::rename(&temp, &self.path)?));
+
+        if let Err(e) = res {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+
+        sync_parent_dir(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Flush the directory entry of a renamed file, so the rename itself survives a crash.<br>
+/// Directories can't be opened as files on Windows, where renames don't need this
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn ignore_not_found(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
@@ -1,5 +1,11 @@
+use std::ops::RangeInclusive;
+
 use crate::define_tag;
 
+/// Tags reserved for optional data of future format versions.<br>
+/// Such tag is followed by data length as varint encoded `usize` and the data, so readers can skip it
+pub const RESERVED_SKIPPABLE_TAGS: RangeInclusive<u8> = 0xe0..=0xfe;
+
 define_tag! {
     #[repr(u8)]
     #[unpack(TypeTag)]
@@ -215,6 +221,18 @@ define_tag! {
         #[doc = "struct variant, name as `Self::StrNew` data and `Self::Struct` data follow"]
         StructVariantStrNew = 48,
 
+        #[unpack(exact PrimitiveKeyMap)]
+        #[doc = "`[(K, T)]` with integer, char or tuple of those keys, length as varint encoded `usize`,"]
+        #[doc = " key type as one byte of integer or char tag, or as `KeyType::Tuple`,"]
+        #[doc = " and pairs of untagged key data and objects follow"]
+        PrimitiveKeyMap = 49,
+
+        #[unpack(exact ExternalBytes)]
+        #[doc = "`[u8]` stored outside of the data,"]
+        #[doc = " reference strlen as varint encoded `usize`"]
+        #[doc = " and reference string encoded as utf8 follow"]
+        ExternalBytes = 50,
+
         #[unpack(exact End)]
         #[doc = "End marker for Seq and Map"]
         End = 255,
@@ -300,6 +318,7 @@ pub enum TypeTag {
     StrDirect,
     EmptyStr,
     Bytes,
+    ExternalBytes,
     Option(OptionTag),
     Struct(StructType),
     EnumVariant {
@@ -313,6 +332,7 @@ pub enum TypeTag {
     Map {
         has_length: bool,
     },
+    PrimitiveKeyMap,
     End,
 }
 
@@ -328,12 +348,14 @@ impl TypeTag {
             TypeTag::StrDirect => None,
             TypeTag::EmptyStr => None,
             TypeTag::Bytes => None,
+            TypeTag::ExternalBytes => None,
             TypeTag::Option(_) => None,
             TypeTag::Struct(_) => None,
             TypeTag::EnumVariant { str, .. } => Some(str),
             TypeTag::Seq { .. } => None,
             TypeTag::Tuple => None,
             TypeTag::Map { .. } => None,
+            TypeTag::PrimitiveKeyMap => None,
             TypeTag::End => None,
         }
     }
@@ -349,12 +371,14 @@ impl TypeTag {
             TypeTag::StrDirect => None,
             TypeTag::EmptyStr => None,
             TypeTag::Bytes => None,
+            TypeTag::ExternalBytes => None,
             TypeTag::Option(_) => None,
             TypeTag::Struct(_) => None,
             TypeTag::EnumVariant { str, .. } => Some(str),
             TypeTag::Seq { .. } => None,
             TypeTag::Tuple => None,
             TypeTag::Map { .. } => None,
+            TypeTag::PrimitiveKeyMap => None,
             TypeTag::End => None,
         }
     }
@@ -390,6 +414,7 @@ impl TypeTag {
             TypeTag::EmptyStr => &[],
 
             TypeTag::Bytes => &[TagParameter::VarintLengthPrefixedBytearray],
+            TypeTag::ExternalBytes => &[TagParameter::VarintLengthPrefixedBytearray],
             TypeTag::Option(OptionTag::None) => &[],
             TypeTag::Option(OptionTag::Some) => &[],
 
@@ -420,11 +445,23 @@ impl TypeTag {
             TypeTag::Tuple => &[TagParameter::Varint],
             TypeTag::Map { has_length: true } => &[TagParameter::Varint],
             TypeTag::Map { has_length: false } => &[],
+            TypeTag::PrimitiveKeyMap => &[TagParameter::Varint, TagParameter::FixedIntBytes(IntWidth::W8)],
             TypeTag::End => &[],
         }
     }
 }
 
+/// Type of untagged keys of a primitive key map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyType {
+    /// Integer or char, written as its tag
+    Primitive(TypeTag),
+
+    /// Tuple of integers and chars, such as coordinates,
+    /// written as `Tuple` tag, element count as varint encoded `usize` and a tag of each element
+    Tuple(Box<[TypeTag]>),
+}
+
 pub enum TagParameter {
     FixedIntBytes(IntWidth),
     Varint,
@@ -459,3 +496,13 @@ pub const _VALIDATE_STR: () = {
         i += 1;
     }
 };
+
+pub const _VALIDATE_RESERVED: () = {
+    let mut i = 0;
+
+    while i < FlatTypeTag::ALL.len() {
+        let tag = FlatTypeTag::ALL[i] as u8;
+        assert!(tag < *RESERVED_SKIPPABLE_TAGS.start() || tag > *RESERVED_SKIPPABLE_TAGS.end());
+        i += 1;
+    }
+};
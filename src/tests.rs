@@ -2,7 +2,7 @@ use std::{collections::HashMap, fmt, io};
 
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
 
-use crate::{RawValue, FORMAT_VERSION};
+use crate::{debug::HexDump, RawValue, FORMAT_VERSION};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 enum Enum {
@@ -62,6 +62,7 @@ impl<V: fmt::Debug> fmt::Debug for NoLenSerialize<V> {
 }
 
 #[test]
+#[cfg_attr(not(feature = "int128"), ignore = "uses u128")]
 fn test_reserialize_complex() {
     let data = Struct {
         values: HashMap::from_iter([
@@ -85,6 +86,7 @@ fn test_reserialize_complex() {
 }
 
 #[test]
+#[cfg_attr(not(feature = "int128"), ignore = "uses u128")]
 fn test_raw() {
     let data = Struct {
         values: HashMap::from_iter([
@@ -112,13 +114,13 @@ fn test_raw() {
     data.serialize(&mut ser).unwrap();
 
     println!("Serialized data:");
-    hexdump(&vec);
+    print!("{}", HexDump(&vec));
 
     let mut de = super::de::Deserializer::new(io::Cursor::new(vec)).unwrap();
     let with_raw = StructWithRaw::deserialize(&mut de).unwrap();
 
     println!("RawValue data:");
-    hexdump(with_raw.e.bytes());
+    print!("{}", HexDump(with_raw.e.bytes()));
 
     let mut de = super::de::Deserializer::new_bare(io::Cursor::new(with_raw.e.bytes()), FORMAT_VERSION);
     let inner = Vec::<Enum>::deserialize(&mut de).unwrap();
@@ -130,7 +132,7 @@ fn test_raw() {
     with_raw.serialize(&mut ser).unwrap();
 
     println!("Reserialized data bytes:");
-    hexdump(&re_vec);
+    print!("{}", HexDump(&re_vec));
 
     let mut de = super::de::Deserializer::new(io::Cursor::new(re_vec)).unwrap();
     let reserialized = Struct::deserialize(&mut de).unwrap();
@@ -145,6 +147,37 @@ fn test_raw() {
 
 }
 
+#[test]
+fn test_raw_string_remapping() {
+    #[derive(Serialize, Deserialize)]
+    struct Captured {
+        other: Vec<String>,
+        raw: RawValue,
+    }
+
+    // Strings of the raw value are interned after unrelated ones, so their ids differ between documents
+    let source = (
+        vec!["unrelated".to_string(), "shared".into()],
+        vec!["shared".to_string(), "only raw".into(), "shared".into()],
+    );
+    let bytes = crate::to_bytes(&source).unwrap();
+    let captured: Captured = crate::from_bytes(&bytes).unwrap();
+
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new(&mut vec, 256).unwrap();
+    "only raw".serialize(&mut ser).unwrap();
+    captured.raw.write_into(&mut ser).unwrap();
+    captured.raw.write_into(&mut ser).unwrap();
+    drop(ser);
+
+    let mut de = super::de::Deserializer::new(vec.as_slice()).unwrap();
+    assert_eq!(String::deserialize(&mut de).unwrap(), "only raw");
+    for _ in 0..2 {
+        assert_eq!(Vec::<String>::deserialize(&mut de).unwrap(), source.1);
+    }
+    assert_eq!(de.string_map_stats().count, 2);
+}
+
 fn test_reserialize<'de, T: Serialize + Deserialize<'de> + Eq + fmt::Debug>(data: &T) {
     println!("Data before serializing: {data:?}");
 
@@ -154,7 +187,7 @@ fn test_reserialize<'de, T: Serialize + Deserialize<'de> + Eq + fmt::Debug>(data
 
     data.serialize(&mut ser).unwrap();
 
-    hexdump(&vec);
+    print!("{}", HexDump(&vec));
 
     let mut de = super::de::Deserializer::new(io::Cursor::new(vec)).unwrap();
 
@@ -167,46 +200,2058 @@ fn test_reserialize<'de, T: Serialize + Deserialize<'de> + Eq + fmt::Debug>(data
     }
 }
 
-fn hexdump(bytes: &[u8]) {
-    for row in 0.. {
+#[test]
+fn test_string_hook() {
+    use std::sync::{Arc, Mutex};
 
-        print!("  ");
+    let data = vec!["a".to_string(), "b".into(), "a".into(), "a".into()];
+    let bytes = crate::to_bytes(&data).unwrap();
 
-        for col in 0..16 {
-            let index = row * 16 + col;
-            if index >= bytes.len() {
-                print!("   ");
-            }
-            else {
-                print!("{:02x} ", bytes[index]);
-            }
+    let seen = Arc::new(Mutex::new(Vec::<Arc<str>>::new()));
+    let hook_seen = seen.clone();
 
-            if col % 4 == 3 {
-                print!(" ");
-            }
+    let mut de = super::de::Deserializer::new(io::Cursor::new(bytes))
+        .unwrap()
+        .with_string_hook(move |s| {
+            hook_seen.lock().unwrap().push(s.clone());
+            s
+        });
+    let re = Vec::<String>::deserialize(&mut de).unwrap();
+
+    assert_eq!(re, data);
+    assert_eq!(*seen.lock().unwrap(), [Arc::from("a"), Arc::from("b")]);
+}
+
+#[test]
+fn test_float_policy() {
+    use crate::{de::DeserializeError, ser::SerializeError, FloatPolicy};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Inner {
+        value: f64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Outer {
+        inner: Inner,
+    }
+
+    let data = Outer {
+        inner: Inner { value: f64::INFINITY },
+    };
+
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new(&mut vec, 256)
+        .unwrap()
+        .with_float_policy(FloatPolicy::FiniteOnly);
+    let err = data.serialize(&mut ser).unwrap_err();
+    assert!(matches!(err, SerializeError::NonFiniteFloat(path) if path == "inner.value"));
+
+    let bytes = crate::to_bytes(&data).unwrap();
+    let mut de = super::de::Deserializer::new(io::Cursor::new(&bytes))
+        .unwrap()
+        .with_float_policy(FloatPolicy::FiniteOnly);
+    let err = Outer::deserialize(&mut de).unwrap_err();
+    assert!(matches!(err, DeserializeError::NonFiniteFloat(path) if path == "inner.value"));
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Nested {
+        values: Vec<f64>,
+        map: HashMap<String, (f32, f32)>,
+        ids: HashMap<u32, f32>,
+    }
+
+    let paths = [
+        (Nested { values: vec![1.0, f64::NAN], map: HashMap::new(), ids: HashMap::new() }, "values[1]"),
+        (Nested { values: vec![], map: HashMap::from([("k".into(), (0.0, f32::NAN))]), ids: HashMap::new() }, "map[\"k\"][1]"),
+        (Nested { values: vec![], map: HashMap::new(), ids: HashMap::from([(3, f32::NAN)]) }, "ids[3]"),
+    ];
+    for (data, expected) in paths {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_float_policy(FloatPolicy::FiniteOnly);
+        let err = data.serialize(&mut ser).unwrap_err();
+        assert!(matches!(&err, SerializeError::NonFiniteFloat(path) if path == expected), "{err}");
+
+        // Non-string keys aren't named when reading
+        let expected = expected.replace("[3]", "[..]");
+        let bytes = crate::to_bytes(&data).unwrap();
+        let mut de = super::de::Deserializer::new(bytes.as_slice())
+            .unwrap()
+            .with_float_policy(FloatPolicy::FiniteOnly);
+        let err = Nested::deserialize(&mut de).unwrap_err();
+        assert!(matches!(&err, DeserializeError::NonFiniteFloat(path) if *path == expected), "{err}");
+    }
+
+    let nan = f32::from_bits(f32::NAN.to_bits() | 1);
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new(&mut vec, 256)
+        .unwrap()
+        .with_float_policy(FloatPolicy::CanonicalNaN);
+    nan.serialize(&mut ser).unwrap();
+    let read: f32 = crate::from_bytes(&vec).unwrap();
+    assert_eq!(read.to_bits(), f32::NAN.to_bits());
+}
+
+#[test]
+fn test_multiple_values_in_stream() {
+    let mut vec = vec![];
+    let first = crate::to_writer_mut(&(1u32, "first"), &mut vec).unwrap();
+    let second = crate::to_writer_mut(&vec![String::from("second")], &mut vec).unwrap();
+    assert_eq!(first + second, vec.len());
+
+    let mut cur = io::Cursor::new(&vec);
+    let a: (u32, String) = crate::from_reader_mut(&mut cur).unwrap();
+    let b: Vec<String> = crate::from_reader_mut(&mut cur).unwrap();
+
+    assert_eq!(a, (1, "first".into()));
+    assert_eq!(b, ["second"]);
+    assert_eq!(cur.position() as usize, vec.len());
+}
+
+#[test]
+fn test_write_read_value() {
+    let mut vec = vec![];
+    let mut ser = crate::Serializer::new(&mut vec, 255).unwrap();
+    ser.write_value(&"shared").unwrap();
+    ser.write_value(&vec!["shared", "shared"]).unwrap();
+    assert_eq!(vec.windows(6).filter(|w| *w == b"shared").count(), 1);
+
+    let mut de = crate::Deserializer::new(vec.as_slice()).unwrap();
+    assert_eq!(de.read_value::<String>().unwrap(), "shared");
+    assert_eq!(de.read_value::<Vec<String>>().unwrap(), ["shared", "shared"]);
+}
+
+#[test]
+fn test_docs() {
+    let records = (0..100u32).map(|i| (i, format!("record{}", i % 3)));
+    let mut vec = vec![];
+    assert_eq!(crate::write_all_docs(records.clone(), &mut vec).unwrap(), 100);
+
+    let mut docs = crate::read_docs(vec.as_slice()).unwrap();
+    let read: Vec<(u32, String)> = docs.by_ref().collect::<Result<_, _>>().unwrap();
+    assert!(read.into_iter().eq(records));
+    assert_eq!(docs.into_inner().string_map_stats().count, 0);
+
+    let mut docs = crate::read_docs::<(u32, String), _>(&vec[..vec.len() - 1]).unwrap();
+    assert_eq!(docs.by_ref().filter(Result::is_ok).count(), 99);
+    assert!(docs.next().is_none());
+
+    assert_eq!(crate::read_docs::<u32, _>(&vec[..3]).unwrap().count(), 0);
+}
+
+#[test]
+fn test_dyn_io() {
+    let mut vec = vec![];
+    crate::to_dyn_writer(&("dyn", 5u32), &mut vec).unwrap();
+    assert_eq!(vec, crate::to_bytes(&("dyn", 5u32)).unwrap());
+
+    let mut reader: &[u8] = &vec;
+    assert_eq!(crate::from_dyn_reader::<(String, u32)>(&mut reader).unwrap(), ("dyn".into(), 5));
+}
+
+#[test]
+fn test_stream_writer() {
+    use crate::{de::StreamReader, ser::StreamWriter};
+
+    let mut stream = StreamWriter::new(vec![]).unwrap();
+    for i in 0..10u32 {
+        stream.write(&(i, "event")).unwrap();
+    }
+    assert_eq!(stream.written(), 10);
+    let log = stream.into_inner();
+    assert_eq!(log.windows(5).filter(|w| *w == b"event").count(), 1);
+
+    let de = crate::Deserializer::new(log.as_slice()).unwrap();
+    let records: Vec<(u32, String)> = StreamReader::new(de).collect::<Result<_, _>>().unwrap();
+    assert_eq!(records.len(), 10);
+    assert_eq!(records[9], (9, "event".into()));
+
+    let mut stream = StreamWriter::new(vec![]).unwrap();
+    for i in 0..10u32 {
+        stream.write(&format!("event {i}")).unwrap();
+        if i % 2 == 1 {
+            stream.clear_string_map();
         }
+    }
+    let log = stream.into_inner();
 
-        for col in 0..16 {
-            let index = row * 16 + col;
-            if index >= bytes.len() {
-                print!(" ");
-            }
-            else {
-                let char = char::from_u32(bytes[index] as u32);
-                let char = char.unwrap_or('.');
-                let char = if char.is_control() { '.' } else { char };
-                print!("{char}");
-            }
+    let mut reader = StreamReader::<String, _>::new(crate::Deserializer::new(log.as_slice()).unwrap());
+    let records: Vec<String> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+    assert_eq!(records[9], "event 9");
+    assert_eq!(reader.into_inner().string_map_stats().count, 2);
+}
 
-            if col % 8 == 7 {
-                print!(" ");
-            }
+#[test]
+fn test_from_bytes_prefix() {
+    let mut vec = crate::to_bytes(&Some(5u8)).unwrap();
+    let first_len = vec.len();
+    vec.extend(crate::to_bytes(&"next").unwrap());
+
+    let (first, read) = crate::from_bytes_prefix::<Option<u8>>(&vec).unwrap();
+    assert_eq!((first, read), (Some(5), first_len));
+
+    let (second, read) = crate::from_bytes_prefix::<String>(&vec[first_len..]).unwrap();
+    assert_eq!(second, "next");
+    assert_eq!(first_len + read, vec.len());
+}
+
+#[test]
+fn test_tuned_cache() {
+    let unique: Vec<String> = (0..64).map(|i| format!("unique string {i}")).collect();
+
+    let default = crate::to_bytes(&unique).unwrap();
+    let tuned = crate::to_bytes_tuned_cache(&unique).unwrap();
+
+    assert!(tuned.len() < default.len());
+    assert_eq!(crate::from_bytes::<Vec<String>>(&tuned).unwrap(), unique);
+}
+
+#[test]
+fn test_profiler() {
+    let data = vec![Enum::A(1), Enum::B, Enum::C("str".into(), 2, 3)];
+    let bytes = crate::to_bytes(&data).unwrap();
+
+    let mut de = super::de::Deserializer::new(io::Cursor::new(&bytes))
+        .unwrap()
+        .with_profiler();
+    Vec::<Enum>::deserialize(&mut de).unwrap();
+
+    let entry = de.profile().unwrap().get("Enum").unwrap();
+    assert_eq!(entry.count, 3);
+    assert!(entry.bytes > 0 && entry.bytes < bytes.len() as u64);
+    assert_eq!(de.position(), bytes.len() as u64);
+
+    // Fields read ahead are deserialized separately, their types are still recorded
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper {
+        items: Vec<Enum>,
+    }
+
+    let bytes = crate::to_bytes(&Wrapper { items: data }).unwrap();
+    let mut de = super::de::Deserializer::new(io::Cursor::new(&bytes))
+        .unwrap()
+        .with_duplicate_fields(crate::de::DuplicateFieldPolicy::LastWins)
+        .with_profiler();
+    Wrapper::deserialize(&mut de).unwrap();
+    assert_eq!(de.profile().unwrap().get("Enum").unwrap().count, 3);
+    assert_eq!(de.profile().unwrap().get("Wrapper").unwrap().count, 1);
+}
+
+#[test]
+fn test_sorted_maps() {
+    fn serialize_sorted(data: &HashMap<String, Vec<u32>>) -> Vec<u8> {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_sorted_maps(true);
+        data.serialize(&mut ser).unwrap();
+        vec
+    }
+
+    let entries = (0..32).map(|i| (format!("key {i}"), vec![i, i * 2]));
+    let a = HashMap::<String, Vec<u32>>::from_iter(entries.clone());
+    let b = HashMap::<String, Vec<u32>>::from_iter(entries.rev());
+
+    let a_bytes = serialize_sorted(&a);
+    assert_eq!(a_bytes, serialize_sorted(&b));
+    assert_eq!(crate::from_bytes::<HashMap<String, Vec<u32>>>(&a_bytes).unwrap(), a);
+}
+
+#[test]
+fn test_compact_map_keys() {
+    fn serialize(data: &impl Serialize, compact: bool, sorted: bool) -> Vec<u8> {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_compact_map_keys(compact)
+            .with_sorted_maps(sorted);
+        data.serialize(&mut ser).unwrap();
+        vec
+    }
+
+    let data = HashMap::<u32, String>::from_iter((0..64).map(|i| (i * 1000, format!("value {i}"))));
+    let compact = serialize(&data, true, false);
+    assert!(compact.len() < serialize(&data, false, false).len());
+    assert_eq!(crate::from_bytes::<HashMap<u32, String>>(&compact).unwrap(), data);
+
+    let sorted = serialize(&data, true, true);
+    assert_eq!(crate::from_bytes::<HashMap<u32, String>>(&sorted).unwrap(), data);
+
+    let raw: RawValue = crate::from_bytes(&compact).unwrap();
+    assert_eq!(raw.deserialize_into::<HashMap<u32, String>>().unwrap(), data);
+    let reserialized = serialize(&raw, false, false);
+    assert_eq!(crate::from_bytes::<HashMap<u32, String>>(&reserialized).unwrap(), data);
+
+    let chars = HashMap::<char, u8>::from_iter([('a', 1), ('ж', 2), ('🦀', 3)]);
+    let bytes = serialize(&chars, true, false);
+    assert_eq!(crate::from_bytes::<HashMap<char, u8>>(&bytes).unwrap(), chars);
+
+    let signed = HashMap::<i8, u8>::from_iter([(-1, 1), (0, 2), (1, 3)]);
+    let bytes = serialize(&signed, true, false);
+    assert_eq!(crate::from_bytes::<HashMap<i8, u8>>(&bytes).unwrap(), signed);
+
+    let strings = HashMap::<String, u32>::from_iter([("a".into(), 1), ("b".into(), 2)]);
+    let bytes = serialize(&strings, true, false);
+    assert_eq!(crate::from_bytes::<HashMap<String, u32>>(&bytes).unwrap(), strings);
+}
+
+#[test]
+fn test_map_reader_raw_rest() {
+    use std::collections::BTreeMap;
+
+    let data = BTreeMap::from_iter((0..6u32).map(|i| (i, format!("value {i}"))));
+    for compact in [false, true] {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_compact_map_keys(compact);
+        data.serialize(&mut ser).unwrap();
+
+        let mut de = crate::Deserializer::new(vec.as_slice()).unwrap();
+        let mut map = de.read_map().unwrap();
+        assert_eq!(map.read_entry::<u32, String>().unwrap(), Some((0, "value 0".into())));
+        assert_eq!(map.read_key::<u32>().unwrap(), Some(1));
+
+        let rest = map.into_raw_rest().unwrap();
+        let value: crate::value::Value = rest.deserialize_into().unwrap();
+        let crate::value::Value::Map(entries) = value else { panic!("expected map, got {value:?}") };
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].0, crate::value::Value::Unit);
+        assert_eq!(entries[0].1, crate::value::Value::Str("value 1".into()));
+        assert_eq!(entries[4].0, crate::value::Value::UInt(5));
+    }
+
+    // Maps without length stay without length
+    let bytes = crate::to_bytes(&NoLenMap(vec![("a", 1), ("b", 2)])).unwrap();
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap();
+    let mut map = de.read_map().unwrap();
+    map.skip_entry().unwrap();
+    let rest = map.into_raw_rest().unwrap();
+    assert_eq!(rest.deserialize_into::<HashMap<String, u32>>().unwrap(), HashMap::from_iter([("b".into(), 2)]));
+}
+
+struct NoLenMap<K, V>(Vec<(K, V)>);
+
+impl<K: Serialize, V: Serialize> Serialize for NoLenMap<K, V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        for (k, v) in &self.0 {
+            map.serialize_entry(k, v)?;
         }
+        map.end()
+    }
+}
+
+#[cfg(feature = "indexmap")]
+#[test]
+fn test_indexmap() {
+    use indexmap::{IndexMap, IndexSet};
+
+    let config = IndexMap::<String, u32>::from_iter([("zeta".into(), 1), ("alpha".into(), 2), ("mid".into(), 3)]);
+    let bytes = crate::to_bytes(&config).unwrap();
+    let read: IndexMap<String, u32> = crate::from_bytes(&bytes).unwrap();
+    assert!(read.iter().eq(config.iter()));
+
+    let ids = IndexMap::<u32, char>::from_iter([(30, 'c'), (10, 'a'), (20, 'b')]);
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new(&mut vec, 256)
+        .unwrap()
+        .with_compact_map_keys(true);
+    ids.serialize(&mut ser).unwrap();
+    let read: IndexMap<u32, char> = crate::from_bytes(&vec).unwrap();
+    assert!(read.iter().eq(ids.iter()));
+
+    let set = IndexSet::<&str>::from_iter(["b", "c", "a"]);
+    let read: IndexSet<String> = crate::from_bytes(&crate::to_bytes(&set).unwrap()).unwrap();
+    assert!(read.iter().eq(set.iter()));
+}
+
+#[test]
+fn test_compact_structs() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        timestamp: u64,
+        temperature: f32,
+        sensor: String,
+        kind: Enum,
+    }
+
+    #[derive(Serialize)]
+    struct Sparse {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<u32>,
+    }
+
+    fn serialize(data: &impl Serialize, compact: bool) -> Result<Vec<u8>, super::ser::SerializeError> {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_compact_structs(compact);
+        data.serialize(&mut ser)?;
+        Ok(vec)
+    }
+
+    let samples: Vec<Sample> = (0..4)
+        .map(|i| Sample {
+            timestamp: 1000 + i,
+            temperature: 20.5,
+            sensor: "outside".into(),
+            kind: Enum::D { v: NoLenSerialize(vec![i as u32]) },
+        })
+        .collect();
+
+    let named = serialize(&samples, false).unwrap();
+    let compact = serialize(&samples, true).unwrap();
+    assert!(compact.len() < named.len());
+
+    let mut de = crate::de::Deserializer::new(compact.as_slice()).unwrap().with_compact_structs(true);
+    let read = Vec::<Sample>::deserialize(&mut de).unwrap();
+    assert_eq!(read, samples);
+
+    // Struct variants aren't read positionally from tuple variants unless compact structs are enabled
+    assert!(crate::from_bytes::<Vec<Sample>>(&compact).is_err());
+
+    #[derive(Serialize)]
+    enum Pair {
+        P(u32, u32),
+    }
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    enum Point {
+        P { x: u32, y: u32 },
+    }
+    let tuple = crate::to_bytes(&Pair::P(1, 2)).unwrap();
+    assert!(crate::from_bytes::<Point>(&tuple).is_err());
+
+    assert!(serialize(&Sparse { value: Some(1) }, true).is_ok());
+    assert!(matches!(
+        serialize(&Sparse { value: None }, true),
+        Err(super::ser::SerializeError::SkippedCompactField("value"))
+    ));
+}
+
+#[test]
+fn test_compact_tuple_keys() {
+    use crate::{debug::AnnotatedDump, value::Value};
+
+    #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct ChunkPos(i32, i32);
+
+    fn serialize(data: &impl Serialize, compact: bool) -> Vec<u8> {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new(&mut vec, 256)
+            .unwrap()
+            .with_compact_map_keys(compact);
+        data.serialize(&mut ser).unwrap();
+        vec
+    }
+
+    let chunks = HashMap::<(i32, i32), u8>::from_iter((-8..8).flat_map(|x| (-8..8).map(move |y| ((x, y), 1))));
+    let compact = serialize(&chunks, true);
+    assert!(compact.len() < serialize(&chunks, false).len());
+    assert_eq!(crate::from_bytes::<HashMap<(i32, i32), u8>>(&compact).unwrap(), chunks);
+    assert!(AnnotatedDump::new(&compact).to_string().contains("key tuple"));
+
+    let raw: RawValue = crate::from_bytes(&compact).unwrap();
+    assert_eq!(raw.deserialize_into::<HashMap<(i32, i32), u8>>().unwrap(), chunks);
+    let value: Value = crate::from_bytes(&compact).unwrap();
+    let Value::Map(entries) = &value else { panic!("expected map, got {value:?}") };
+    assert!(entries.iter().all(|(k, _)| matches!(k, Value::Tuple(v) if v.len() == 2)));
+
+    let mut de = crate::Deserializer::new(compact.as_slice()).unwrap();
+    let mut map = de.read_map().unwrap();
+    while map.skip_entry().unwrap() {}
+
+    let structs = HashMap::from_iter([(ChunkPos(0, -1), 'a'), (ChunkPos(5, 3), 'b')]);
+    let bytes = serialize(&structs, true);
+    assert_eq!(crate::from_bytes::<HashMap<ChunkPos, char>>(&bytes).unwrap(), structs);
+
+    let mixed = HashMap::from_iter([((1u32, "a".to_string()), 1u8), ((2, "b".into()), 2)]);
+    let bytes = serialize(&mixed, true);
+    assert_eq!(crate::from_bytes::<HashMap<(u32, String), u8>>(&bytes).unwrap(), mixed);
+}
+
+#[test]
+fn test_columnar() {
+    use crate::columnar::Columnar;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        id: u32,
+        name: String,
+        position: (f32, f32),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Table {
+        #[serde(with = "crate::columnar")]
+        records: Vec<Record>,
+    }
+
+    let records: Vec<Record> = (0..100)
+        .map(|i| Record {
+            id: i,
+            name: format!("record {i}"),
+            position: (i as f32, -(i as f32)),
+        })
+        .collect();
+
+    let columnar = crate::to_bytes(&Columnar(records.clone())).unwrap();
+    assert!(columnar.len() < crate::to_bytes(&records).unwrap().len());
+    assert_eq!(crate::from_bytes::<Columnar<Vec<Record>>>(&columnar).unwrap().0, records);
+
+    let table = Table { records };
+    let bytes = crate::to_bytes(&table).unwrap();
+    assert_eq!(crate::from_bytes::<Table>(&bytes).unwrap(), table);
+
+    let empty = crate::to_bytes(&Columnar(Vec::<Record>::new())).unwrap();
+    assert!(crate::from_bytes::<Columnar<Vec<Record>>>(&empty).unwrap().0.is_empty());
+
+    assert!(crate::to_bytes(&Columnar(vec![1, 2, 3])).is_err());
+
+    #[derive(Serialize)]
+    struct Sparse {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<u32>,
+    }
+
+    let err = crate::to_bytes(&Columnar(vec![Sparse { value: Some(1) }, Sparse { value: None }])).unwrap_err();
+    assert!(err.to_string().contains("field \"value\" was skipped"), "{err}");
+
+    // Columns are matched to fields by name
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Reordered {
+        name: String,
+        id: u32,
+    }
+
+    let read = crate::from_bytes::<Columnar<Vec<Reordered>>>(&columnar).unwrap().0;
+    assert_eq!(read[5], Reordered { name: "record 5".into(), id: 5 });
+}
+
+#[test]
+fn test_verify() {
+    let bytes = crate::to_bytes(&(String::from("verified"), vec![1u32, 2, 3], HashMap::from([(1u8, "a")]))).unwrap();
+    crate::verify(bytes.as_slice()).unwrap();
+
+    assert!(crate::verify(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut broken = bytes.clone();
+    broken[3] = 200;
+    assert!(matches!(crate::verify(broken.as_slice()), Err(crate::de::DeserializeError::InvalidTag(200))));
+
+    let followed = [bytes.as_slice(), &[0]].concat();
+    assert!(matches!(crate::verify(followed.as_slice()), Err(crate::de::DeserializeError::TrailingData)));
+
+    crate::verify_as::<(String, Vec<u32>, HashMap<u8, String>), _>(bytes.as_slice()).unwrap();
+    assert!(crate::verify_as::<(String, Vec<String>, HashMap<u8, String>), _>(bytes.as_slice()).is_err());
+}
+
+#[test]
+fn test_duplicate_fields() {
+    use crate::de::{Deserializer, DuplicateFieldPolicy};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Fields {
+        a: u32,
+        b: u32,
+    }
+
+    fn read(bytes: &[u8], policy: DuplicateFieldPolicy) -> Result<Fields, crate::de::DeserializeError> {
+        let mut de = Deserializer::new(bytes).unwrap().with_duplicate_fields(policy);
+        Fields::deserialize(&mut de)
+    }
+
+    // Written by hand, serializer won't write duplicated fields with debug checks
+    #[rustfmt::skip]
+    let bytes = [
+        b's', b'd', 0,
+        46, 3,             // struct, 3 fields
+        26, 0, 1, b'a',    // new string 0 "a"
+        15, 1,             // u32 1
+        26, 1, 1, b'b',    // new string 1 "b"
+        15, 2,             // u32 2
+        25, 0,             // string 0 "a"
+        15, 3,             // u32 3
+    ];
+    assert!(read(&bytes, DuplicateFieldPolicy::Error).is_err());
+    assert_eq!(read(&bytes, DuplicateFieldPolicy::FirstWins).unwrap(), Fields { a: 1, b: 2 });
+    assert_eq!(read(&bytes, DuplicateFieldPolicy::LastWins).unwrap(), Fields { a: 3, b: 2 });
+
+    let bytes = crate::to_bytes(&vec![Enum::D { v: NoLenSerialize(vec![1, 2]) }]).unwrap();
+    let mut de = Deserializer::new(bytes.as_slice()).unwrap().with_duplicate_fields(DuplicateFieldPolicy::LastWins);
+    assert_eq!(Vec::<Enum>::deserialize(&mut de).unwrap(), vec![Enum::D { v: NoLenSerialize(vec![1, 2]) }]);
+}
+
+#[test]
+fn test_read_bytes_into() {
+    use crate::{bytes::ByteBuf, de::DeserializeError, Deserializer};
+
+    let bytes = crate::to_bytes(&ByteBuf(vec![3; 100])).unwrap();
+
+    let mut buf = [0; 100];
+    Deserializer::new(bytes.as_slice()).unwrap().read_bytes_into(&mut buf).unwrap();
+    assert_eq!(buf, [3; 100]);
+
+    let res = Deserializer::new(bytes.as_slice()).unwrap().read_bytes_into(&mut [0; 10]);
+    assert!(matches!(res, Err(DeserializeError::LengthMismatch { expected: 10, read: 100 })));
+}
+
+#[test]
+fn test_map_reader() {
+    use crate::de::Deserializer;
+
+    let chunks = HashMap::<u32, Vec<String>>::from_iter((0..16).map(|i| (i, vec![format!("chunk {i}"); 2])));
 
-        println!();
+    for compact in [false, true] {
+        let mut bytes = vec![];
+        let mut ser = super::ser::Serializer::new(&mut bytes, 256)
+            .unwrap()
+            .with_compact_map_keys(compact);
+        chunks.serialize(&mut ser).unwrap();
+
+        let mut de = Deserializer::new(bytes.as_slice()).unwrap();
+        let mut map = de.read_map().unwrap();
+        let mut loaded = HashMap::new();
+        while let Some(key) = map.read_key::<u32>().unwrap() {
+            if key % 2 == 0 {
+                loaded.insert(key, map.read_value::<Vec<String>>().unwrap());
+            } else {
+                map.skip_value().unwrap();
+            }
+        }
+        assert_eq!(loaded.len(), 8);
+        assert_eq!(loaded[&4], chunks[&4]);
 
-        if row * 16 >= bytes.len() {
-            break;
+        let mut de = Deserializer::new(bytes.as_slice()).unwrap();
+        let mut map = de.read_map().unwrap();
+        assert_eq!(map.size_hint(), (chunks.len(), Some(chunks.len())));
+        assert!(map.skip_entry().unwrap());
+        let mut count = 1;
+        while let Some((key, value)) = map.read_entry::<u32, Vec<String>>().unwrap() {
+            assert_eq!(chunks[&key], value);
+            count += 1;
         }
+        assert_eq!(count, chunks.len());
+        assert_eq!(map.remaining(), Some(0));
+        assert!(!map.skip_entry().unwrap());
+    }
+}
+
+#[test]
+fn test_blob_store() {
+    use std::sync::{Arc, Mutex};
+
+    use crate::bytes::ByteBuf;
+
+    let blobs = Arc::new(Mutex::new(HashMap::<String, Vec<u8>>::new()));
+    let data = vec![ByteBuf(vec![1; 4]), ByteBuf(vec![2; 1000])];
+
+    let mut bytes = vec![];
+    let store = blobs.clone();
+    let mut ser = super::ser::Serializer::new(&mut bytes, 256)
+        .unwrap()
+        .with_blob_store(64, move |data| {
+            let mut store = store.lock().unwrap();
+            let name = format!("blob{}", store.len());
+            store.insert(name.clone(), data.to_vec());
+            Ok(name)
+        });
+    data.serialize(&mut ser).unwrap();
+
+    assert_eq!(blobs.lock().unwrap().len(), 1);
+    assert!(bytes.len() < 100);
+
+    assert!(matches!(
+        crate::from_bytes::<Vec<ByteBuf>>(&bytes),
+        Err(crate::de::DeserializeError::NoBlobLoader)
+    ));
+
+    let loader = blobs.clone();
+    let mut de = crate::de::Deserializer::new(bytes.as_slice())
+        .unwrap()
+        .with_blob_loader(move |name| {
+            loader.lock().unwrap().get(name).cloned().ok_or_else(|| io::ErrorKind::NotFound.into())
+        });
+    assert_eq!(Vec::<ByteBuf>::deserialize(&mut de).unwrap(), data);
+}
+
+#[test]
+fn test_ignored_fields() {
+    use crate::bytes::ByteBuf;
+
+    #[derive(Serialize)]
+    struct New {
+        name: &'static str,
+        attachment: ByteBuf,
+        tags: Vec<&'static str>,
+        kind: &'static str,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Old {
+        name: String,
+        kind: String,
+    }
+
+    let new = New {
+        name: "doc",
+        attachment: ByteBuf(vec![7; 1000]),
+        tags: vec!["draft", "shared"],
+        kind: "shared",
+    };
+
+    let mut bytes = vec![];
+    let mut ser = super::ser::Serializer::new(&mut bytes, 256)
+        .unwrap()
+        .with_blob_store(64, |_| Ok("blob".into()));
+    new.serialize(&mut ser).unwrap();
+
+    // Blob of the unknown field isn't loaded, string interned inside it is still read
+    let old: Old = crate::from_bytes(&bytes).unwrap();
+    assert_eq!(old, Old { name: "doc".into(), kind: "shared".into() });
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(unused)]
+    struct Strict {
+        name: String,
+        kind: String,
+    }
+
+    let err = crate::from_bytes::<Strict>(&bytes).unwrap_err();
+    assert!(err.to_string().contains("unknown field `attachment`"), "{err}");
+}
+
+#[test]
+fn test_char_string_interop() {
+    let bytes = crate::to_bytes(&("a", 'b')).unwrap();
+    let (a, b): (char, String) = crate::from_bytes(&bytes).unwrap();
+    assert_eq!((a, b.as_str()), ('a', "b"));
+
+    let bytes = crate::to_bytes(&"ab").unwrap();
+    assert!(crate::from_bytes::<char>(&bytes).is_err());
+}
+
+#[test]
+fn test_truncated_length() {
+    let mut bytes = crate::to_bytes(&"truncated").unwrap();
+    bytes.truncate(5);
+    crate::varint::write_unsigned_varint(&mut bytes, usize::MAX >> 8).unwrap();
+    bytes.extend_from_slice(b"data");
+
+    let res = crate::from_bytes::<String>(&bytes);
+    assert!(matches!(res, Err(crate::de::DeserializeError::IOError(e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn test_skip_serializing_if() {
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Sparse {
+        id: u32,
+
+        #[serde(skip_serializing_if = "Vec::is_empty", default)]
+        tags: Vec<String>,
+
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        parent: Option<u32>,
+    }
+
+    let sparse = Sparse { id: 1, ..Default::default() };
+    let full = Sparse { id: 2, tags: vec!["tag".into()], parent: Some(1) };
+
+    let sparse_bytes = crate::to_bytes(&sparse).unwrap();
+    assert!(sparse_bytes.len() < crate::to_bytes(&full).unwrap().len());
+    assert_eq!(crate::from_bytes::<Sparse>(&sparse_bytes).unwrap(), sparse);
+    assert_eq!(crate::from_bytes::<Sparse>(&crate::to_bytes(&full).unwrap()).unwrap(), full);
+}
+
+#[test]
+fn test_skip_field() {
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Cached {
+        id: u32,
+
+        #[serde(skip)]
+        cache: Vec<u64>,
+    }
+
+    let cached = Cached { id: 4, cache: vec![1, 2, 3] };
+    let bytes = crate::to_bytes(&cached).unwrap();
+    assert_eq!(bytes, crate::to_bytes(&Cached { id: 4, cache: vec![] }).unwrap());
+    assert_eq!(crate::from_bytes::<Cached>(&bytes).unwrap(), Cached { id: 4, cache: vec![] });
+}
+
+#[test]
+fn test_cfg_fields() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Featured {
+        id: u32,
+
+        #[cfg(feature = "int128")]
+        #[serde(default)]
+        wide: u128,
     }
-}
\ No newline at end of file
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Plain {
+        id: u32,
+    }
+
+    // Same struct written by a build with the field's feature disabled and read by one with it enabled, and back
+    let plain = crate::to_bytes(&Plain { id: 3 }).unwrap();
+    let featured: Featured = crate::from_bytes(&plain).unwrap();
+    assert_eq!(featured.id, 3);
+    #[cfg(feature = "int128")]
+    assert_eq!(featured.wide, 0);
+
+    let featured = crate::to_bytes(&featured).unwrap();
+    assert_eq!(crate::from_bytes::<Plain>(&featured).unwrap(), Plain { id: 3 });
+}
+
+#[test]
+fn test_bytes() {
+    use crate::bytes::{ByteBuf, Bytes};
+
+    let data: Vec<u8> = (0..=255).collect();
+    let bytes = crate::to_bytes(&(Bytes(&data), "borrowed")).unwrap();
+    assert!(bytes.len() < crate::to_bytes(&data).unwrap().len());
+
+    let (buf, str): (ByteBuf, String) = crate::from_bytes(&bytes).unwrap();
+    assert_eq!(buf.0, data);
+    assert_eq!(str, "borrowed");
+}
+
+#[test]
+fn test_annotated_dump() {
+    use crate::debug::AnnotatedDump;
+
+    let mut bytes = vec![];
+    let mut ser = super::ser::Serializer::new(&mut bytes, 256)
+        .unwrap()
+        .with_compact_map_keys(true);
+    (HashMap::from([(7u32, vec!["seven"])]), Some(Enum::B)).serialize(&mut ser).unwrap();
+
+    let dump = AnnotatedDump::new(&bytes).to_string();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines[0], "00000000  header  73 64 00");
+    assert!(lines[1].ends_with("Tuple  02"));
+    assert!(lines[2].contains("  PrimitiveKeyMap  01 0f"));
+    assert!(lines[3].ends_with("    key  07"));
+    assert!(lines[5].contains("      StrNew  00 05 73 65 76 65 6e"));
+    assert!(lines[6].contains("  Some"));
+    assert!(lines[7].contains("    UnitVariantStrNew"));
+
+    let counts = AnnotatedDump::new(&bytes).tag_counts();
+    assert_eq!(counts["StrNew"], 1);
+    assert_eq!(counts.values().sum::<usize>(), 6);
+
+    let mut broken = bytes.clone();
+    broken[3] = 200;
+    assert!(AnnotatedDump::new(&broken).to_string().contains("invalid tag 200"));
+
+    let varints = crate::debug::VarintDump::new(&[0, 0xac, 0x02, 0x41, 0x80], 1).to_string();
+    let lines: Vec<&str> = varints.lines().collect();
+    assert_eq!(lines[0], "00000001  ac 02  unsigned 300  signed 172");
+    assert_eq!(lines[1], "00000003  41  unsigned 65  signed -1");
+    assert_eq!(lines[2], "00000004  truncated varint  80");
+}
+
+#[test]
+fn test_reserved_tags() {
+    use crate::de::{DeserializeError, Deserializer};
+
+    let bytes = crate::to_bytes(&vec![1u8, 2]).unwrap();
+    // header, LenSeq tag and length, then an optional future value before the first element
+    let mut future = bytes[..5].to_vec();
+    future.extend_from_slice(&[0xe0, 3, 1, 2, 3]);
+    future.extend_from_slice(&bytes[5..]);
+
+    assert!(matches!(crate::from_bytes::<Vec<u8>>(&future), Err(DeserializeError::ReservedTag(0xe0))));
+
+    let mut de = Deserializer::new(future.as_slice()).unwrap().with_skip_reserved_tags(true);
+    assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn test_string_map_stats() {
+    use crate::de::{DeserializeError, Deserializer, StringMapStats};
+
+    let bytes = crate::to_bytes(&(vec!["first", "second"], vec!["third", "first"])).unwrap();
+    let mut de = Deserializer::new(bytes.as_slice()).unwrap();
+    let _: (Vec<String>, Vec<String>) = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(de.string_map_stats(), StringMapStats { count: 3, bytes: 16 });
+
+    de.clear_string_map();
+    assert_eq!(de.string_map_stats(), StringMapStats { count: 0, bytes: 0 });
+
+    let sections = std::collections::BTreeMap::from([("a", "first"), ("b", "first")]);
+    let bytes = crate::to_bytes(&sections).unwrap();
+    let mut de = Deserializer::new(bytes.as_slice()).unwrap();
+    let mut map = de.read_map().unwrap();
+    assert_eq!(map.read_entry::<String, String>().unwrap().unwrap().1, "first");
+    assert_eq!(map.read_key::<String>().unwrap().unwrap(), "b");
+
+    de.clear_string_map();
+    assert!(matches!(String::deserialize(&mut de), Err(DeserializeError::InvalidStringId(_))));
+}
+
+#[test]
+fn test_smart_pointers() {
+    use std::{borrow::Cow, path::{Path, PathBuf}};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        names: Cow<'static, [String]>,
+        root: Box<Path>,
+        cache: PathBuf,
+    }
+
+    let config = Config {
+        names: Cow::Owned(vec!["a".into(), "b".into()]),
+        root: Path::new("/srv/data").into(),
+        cache: PathBuf::from("cache"),
+    };
+
+    let bytes = crate::to_bytes(&config).unwrap();
+    assert_eq!(crate::from_bytes::<Config>(&bytes).unwrap(), config);
+
+    #[cfg(feature = "rc")]
+    {
+        let path: std::sync::Arc<Path> = Path::new("shared").into();
+        let bytes = crate::to_bytes(&path).unwrap();
+        assert_eq!(crate::from_bytes::<std::sync::Arc<Path>>(&bytes).unwrap(), path);
+    }
+}
+
+crate::repr_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        Empty,
+        Stone = 5,
+        Water,
+    }
+}
+
+#[test]
+fn test_repr_enum() {
+    assert_eq!(u32::from(Kind::Water), 6);
+    assert_eq!(Kind::try_from(5), Ok(Kind::Stone));
+    assert_eq!(Kind::try_from(1), Err(1));
+
+    let kinds = vec![Kind::Empty, Kind::Stone, Kind::Water];
+    let bytes = crate::to_bytes(&kinds).unwrap();
+    assert!(bytes.len() < crate::to_bytes(&vec![Enum::B, Enum::B, Enum::B]).unwrap().len());
+    assert_eq!(crate::from_bytes::<Vec<Kind>>(&bytes).unwrap(), kinds);
+
+    assert!(crate::from_bytes::<Kind>(&crate::to_bytes(&2u32).unwrap()).is_err());
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_frame_codec() {
+    use crate::net::{FrameCodec, FrameError};
+
+    let codec = FrameCodec::new().with_max_size(64);
+    let mut buf = vec![];
+    codec.encode(&"first", &mut buf).unwrap();
+    codec.encode(&vec![1u32, 2, 3], &mut buf).unwrap();
+
+    let mut partial = buf[..buf.len() - 1].to_vec();
+    assert_eq!(codec.decode::<String>(&mut partial).unwrap().as_deref(), Some("first"));
+    assert!(codec.decode::<Vec<u32>>(&mut partial).unwrap().is_none());
+
+    let mut reader = buf.as_slice();
+    assert_eq!(codec.read::<String, _>(&mut reader).unwrap(), "first");
+    assert_eq!(codec.read::<Vec<u32>, _>(&mut reader).unwrap(), vec![1, 2, 3]);
+    assert!(reader.is_empty());
+
+    let large = "x".repeat(100);
+    assert!(matches!(codec.encode(&large, &mut vec![]), Err(FrameError::TooLarge { .. })));
+
+    let mut large_frame = vec![];
+    FrameCodec::new().encode(&large, &mut large_frame).unwrap();
+    assert!(matches!(codec.split(&large_frame[..2]), Err(FrameError::TooLarge { .. })));
+
+    let mut huge_frame = vec![];
+    crate::varint::write_unsigned_varint(&mut huge_frame, usize::MAX).unwrap();
+    let unlimited = FrameCodec::new().with_max_size(usize::MAX);
+    assert!(matches!(unlimited.split(&huge_frame), Err(FrameError::TooLarge { len: usize::MAX, .. })));
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_frame_reader() {
+    use crate::net::{read_framed_from, write_framed_into, FrameError, FrameReader};
+
+    /// Reader returning a single byte per read, like a slow socket
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(1);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let mut buf = vec![];
+    for i in 0..3u32 {
+        write_framed_into(&(i, format!("message {i}")), &mut buf).unwrap();
+    }
+
+    let mut reader = buf.as_slice();
+    assert_eq!(read_framed_from::<(u32, String), _>(&mut reader).unwrap(), (0, "message 0".into()));
+
+    let messages: Vec<(u32, String)> = FrameReader::new(Trickle(&buf)).collect::<Result<_, _>>().unwrap();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[2], (2, "message 2".into()));
+
+    let mut frames = FrameReader::<(u32, String), _>::new(Trickle(&buf[..buf.len() - 1]));
+    assert!(frames.next().unwrap().is_ok());
+    assert!(frames.next().unwrap().is_ok());
+    assert!(matches!(frames.next(), Some(Err(FrameError::IOError(_)))));
+    assert!(frames.next().is_none());
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_indexed_frames() {
+    use crate::net::{FrameError, FrameReader, IndexedFrameReader, IndexedFrameWriter};
+
+    // Frames don't have to start at the start of the data
+    let mut writer = IndexedFrameWriter::new(b"prefix".to_vec()).with_interval(8);
+    for i in 0..100u32 {
+        writer.write_keyed(&(i, format!("entry {i}")), i as i64 * 10).unwrap();
+    }
+    let buf = writer.finish().unwrap();
+
+    let mut reader = IndexedFrameReader::new(io::Cursor::new(buf.as_slice())).unwrap();
+    assert_eq!(reader.len(), 100);
+    for i in [0, 7, 8, 55, 99] {
+        assert_eq!(reader.read::<(u32, String)>(i).unwrap(), Some((i as u32, format!("entry {i}"))));
+    }
+    assert_eq!(reader.read::<(u32, String)>(100).unwrap(), None);
+
+    assert_eq!(reader.find_key(-1), None);
+    assert_eq!(reader.find_key(555), Some(48));
+    let from_key: Vec<(u32, String)> = reader.frames_from(48).unwrap().collect::<Result<_, _>>().unwrap();
+    assert_eq!(from_key.len(), 52);
+    assert_eq!(from_key[7].0, 55);
+
+    // Frames are readable without the index, ending before it
+    let frames: Vec<(u32, String)> = FrameReader::new(&buf[6..]).collect::<Result<_, _>>().unwrap();
+    assert_eq!(frames.len(), 100);
+
+    let empty = IndexedFrameWriter::new(vec![]).finish().unwrap();
+    let mut reader = IndexedFrameReader::new(io::Cursor::new(empty)).unwrap();
+    assert!(reader.is_empty());
+    assert_eq!(reader.read::<u32>(0).unwrap(), None);
+
+    let mut plain = vec![];
+    crate::net::write_framed_into(&"not indexed", &mut plain).unwrap();
+    assert!(matches!(IndexedFrameReader::new(io::Cursor::new(plain)), Err(FrameError::MissingIndex)));
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes_crate() {
+    let mut buf = ::bytes::BytesMut::new();
+    let written = crate::to_bytes_mut(&"first", &mut buf).unwrap();
+    crate::to_bytes_mut(&42u32, &mut buf).unwrap();
+    assert_eq!(written, crate::to_bytes(&"first").unwrap().len());
+
+    let mut buf = buf.freeze();
+    assert_eq!(crate::from_bytes_buf::<String>(&mut buf).unwrap(), "first");
+    assert_eq!(crate::from_bytes_buf::<u32>(&mut buf).unwrap(), 42);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_send_across_threads() {
+    let raw = crate::to_raw(&vec!["a", "b"]).unwrap();
+    let handle = std::thread::spawn(move || -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let value = crate::from_raw(&raw)?;
+        crate::from_bytes::<u8>(&[])?;
+        Ok(value)
+    });
+    assert!(handle.join().unwrap().is_err());
+}
+
+#[test]
+fn test_tolerant_variants() {
+    #[derive(Serialize)]
+    enum Old {
+        Idle,
+        Move { x: i32 },
+        Limit,
+        Named(String),
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum New {
+        Idle {
+            #[serde(default)]
+            since: u32,
+        },
+        Move,
+        Limit(Option<u32>),
+        Named(String),
+    }
+
+    let old = vec![Old::Idle, Old::Move { x: 5 }, Old::Limit, Old::Named("a".into()), Old::Idle];
+    let bytes = crate::to_bytes(&old).unwrap();
+    assert!(crate::from_bytes::<Vec<New>>(&bytes).is_err());
+
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap().with_tolerant_variants(true);
+    assert_eq!(
+        Vec::<New>::deserialize(&mut de).unwrap(),
+        vec![
+            New::Idle { since: 0 },
+            New::Move,
+            New::Limit(None),
+            New::Named("a".into()),
+            New::Idle { since: 0 }
+        ]
+    );
+}
+
+#[test]
+fn test_tolerant_units() {
+    #[derive(Serialize)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Fields {
+        #[serde(default)]
+        count: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Empty {}
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct NewMarker;
+
+    fn read<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, crate::de::DeserializeError> {
+        let mut de = crate::Deserializer::new(bytes).unwrap().with_tolerant_units(true);
+        T::deserialize(&mut de)
+    }
+
+    let marker = crate::to_bytes(&Marker).unwrap();
+    let unit = crate::to_bytes(&()).unwrap();
+    let empty = crate::to_bytes(&Empty {}).unwrap();
+
+    assert!(crate::from_bytes::<Fields>(&marker).is_err());
+    assert!(crate::from_bytes::<NewMarker>(&empty).is_err());
+
+    assert_eq!(read::<Fields>(&marker).unwrap(), Fields { count: 0 });
+    assert_eq!(read::<Fields>(&unit).unwrap(), Fields { count: 0 });
+    assert_eq!(read::<Empty>(&marker).unwrap(), Empty {});
+    assert_eq!(read::<NewMarker>(&empty).unwrap(), NewMarker);
+    read::<()>(&empty).unwrap();
+
+    let fields = crate::to_bytes(&vec![Empty {}, Empty {}]).unwrap();
+    assert_eq!(read::<Vec<NewMarker>>(&fields).unwrap(), vec![NewMarker, NewMarker]);
+}
+
+#[test]
+fn test_peek_variant_name() {
+    let packets = [
+        crate::to_raw(&Enum::B).unwrap(),
+        crate::to_raw(&Enum::A(7)).unwrap(),
+        crate::to_raw(&5u32).unwrap(),
+    ];
+
+    let names: Vec<_> = packets.iter().map(|p| p.peek_variant_name().unwrap()).collect();
+    assert_eq!(names[0].as_deref(), Some("B"));
+    assert_eq!(names[1].as_deref(), Some("A"));
+    assert_eq!(names[2], None);
+
+    assert_eq!(crate::from_raw::<Enum>(&packets[1]).unwrap(), Enum::A(7));
+}
+
+#[allow(dead_code)]
+#[deny(clippy::pedantic)]
+mod repr_enum_hygiene {
+    // Shadowed prelude names, several enums generated by a wrapper macro and strict lints must not break repr_enum
+    type Result<T> = std::result::Result<T, ()>;
+    struct Ok;
+    struct Err;
+
+    macro_rules! wrapped {
+        ($($name:ident),*) => {
+            $(
+                crate::repr_enum! {
+                    #[derive(Debug, PartialEq)]
+                    enum $name { First, Second }
+                }
+            )*
+        };
+    }
+
+    wrapped!(Left, Right);
+
+    #[test]
+    fn test_repr_enum_in_macro() {
+        let bytes = crate::to_bytes(&(Left::Second, Right::First)).unwrap();
+        assert_eq!(crate::from_bytes::<(Left, Right)>(&bytes).unwrap(), (Left::Second, Right::First));
+    }
+}
+
+#[test]
+fn test_intern_policy() {
+    use crate::ser::InternPolicy;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        name: String,
+        kind: Enum,
+    }
+
+    let entries: Vec<_> = (0..300).map(|i| Entry { name: format!("entry{i}"), kind: Enum::B }).collect();
+
+    let all = crate::to_bytes(&entries).unwrap();
+    let mut names_only = vec![];
+    let mut ser = crate::Serializer::new(&mut names_only, 255)
+        .unwrap()
+        .with_intern_policy(InternPolicy::NamesOnly);
+    entries.serialize(&mut ser).unwrap();
+
+    assert!(names_only.len() < all.len());
+    assert_eq!(crate::from_bytes::<Vec<Entry>>(&names_only).unwrap(), entries);
+}
+
+#[test]
+fn test_eq_documents() {
+    use crate::ser::InternPolicy;
+
+    let data: Vec<(String, u32)> = (0..10).map(|i| (format!("key{}", i % 3), i)).collect();
+    let interned = crate::to_bytes(&data).unwrap();
+
+    let mut direct = vec![];
+    let mut ser = crate::Serializer::new(&mut direct, 255)
+        .unwrap()
+        .with_intern_policy(InternPolicy::NamesOnly);
+    data.serialize(&mut ser).unwrap();
+
+    assert_ne!(interned, direct);
+    assert!(crate::eq_documents(interned.as_slice(), direct.as_slice()).unwrap());
+
+    let other = crate::to_bytes(&data[1..].to_vec()).unwrap();
+    assert!(!crate::eq_documents(interned.as_slice(), other.as_slice()).unwrap());
+
+    let signed = crate::to_bytes(&(5i32, vec![Some(7i8)], HashMap::from([(1i64, 2i16)]))).unwrap();
+    let unsigned = crate::to_bytes(&(5u32, vec![Some(7u64)], HashMap::from([(1u8, 2u128)]))).unwrap();
+    assert!(crate::eq_documents(signed.as_slice(), unsigned.as_slice()).unwrap());
+
+    let negative = crate::to_bytes(&(-5i32, vec![Some(7i8)], HashMap::from([(1i64, 2i16)]))).unwrap();
+    assert!(!crate::eq_documents(negative.as_slice(), unsigned.as_slice()).unwrap());
+}
+
+#[test]
+fn test_serialized_size() {
+    let data: Vec<(String, u32)> = (0..100).map(|i| (format!("key{}", i % 7), i)).collect();
+    assert_eq!(crate::serialized_size(&data).unwrap(), crate::to_bytes(&data).unwrap().len());
+    assert_eq!(crate::serialized_size(&()).unwrap(), crate::to_bytes(&()).unwrap().len());
+}
+
+#[test]
+fn test_capture_shape() {
+    use crate::schema::{capture_shape, Shape, ShapeData};
+
+    #[derive(Serialize)]
+    struct Player {
+        name: String,
+        inventory: Vec<(u16, Option<f32>)>,
+        stats: HashMap<String, i64>,
+        mode: Enum,
+    }
+
+    let player = Player {
+        name: "player".into(),
+        inventory: vec![(1, None), (2, Some(0.5))],
+        stats: HashMap::from_iter([("kills".into(), 3)]),
+        mode: Enum::B,
+    };
+
+    let shape = capture_shape(&player).unwrap();
+    let Shape::Struct("Player", ShapeData::Struct(fields)) = &shape else { panic!("expected struct, got {shape:?}") };
+    assert_eq!(fields.iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["name", "inventory", "stats", "mode"]);
+    assert_eq!(fields[0].1, Shape::Str);
+    assert_eq!(
+        fields[1].1,
+        Shape::Seq {
+            len: 2,
+            sample: Some(Box::new(Shape::Tuple(vec![Shape::Int { signed: false, bits: 16 }, Shape::Option(None)]))),
+        }
+    );
+    assert_eq!(
+        fields[2].1,
+        Shape::Map { len: 1, sample: Some(Box::new((Shape::Str, Shape::Int { signed: true, bits: 64 }))) }
+    );
+    assert_eq!(fields[3].1, Shape::Variant("Enum", "B", ShapeData::Unit));
+
+    let tree = shape.to_string();
+    assert!(tree.contains("  inventory: seq (2)\n    []: tuple\n      0: u16\n"), "{tree}");
+}
+
+#[test]
+fn test_field_names() {
+    use crate::schema::{field_names, variant_names};
+
+    #[derive(Deserialize)]
+    #[allow(unused)]
+    struct Renamed {
+        #[serde(rename = "id")]
+        key: u32,
+        value: String,
+    }
+
+    assert_eq!(field_names::<Renamed>(), Some(["id", "value"].as_slice()));
+    assert_eq!(field_names::<Struct>(), Some(["values", "e", "tup"].as_slice()));
+    assert_eq!(variant_names::<Enum>(), Some(["A", "B", "C", "D"].as_slice()));
+    assert_eq!(field_names::<Enum>(), None);
+    assert_eq!(variant_names::<u32>(), None);
+}
+
+#[test]
+fn test_timestamps() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        #[serde(with = "crate::timestamp")]
+        at: SystemTime,
+
+        #[serde(with = "crate::timestamp::duration")]
+        took: Duration,
+    }
+
+    let event = Event { at: UNIX_EPOCH + Duration::new(1_700_000_000, 5), took: Duration::from_millis(1500) };
+    let bytes = crate::to_bytes(&event).unwrap();
+    assert_eq!(crate::from_bytes::<Event>(&bytes).unwrap(), event);
+
+    #[derive(Serialize, Deserialize)]
+    struct Raw {
+        at: (i64, u32),
+        took: (u64, u32),
+    }
+
+    let before_epoch = Event { at: UNIX_EPOCH - Duration::from_millis(250), took: Duration::ZERO };
+    let raw: Raw = crate::from_bytes(&crate::to_bytes(&before_epoch).unwrap()).unwrap();
+    assert_eq!(raw.at, (-1, 750_000_000));
+    assert_eq!(crate::from_bytes::<Event>(&crate::to_bytes(&before_epoch).unwrap()).unwrap(), before_epoch);
+
+    let invalid = crate::to_bytes(&Raw { at: (0, 1_000_000_000), took: (0, 0) }).unwrap();
+    assert!(crate::from_bytes::<Event>(&invalid).is_err());
+
+    #[cfg(feature = "chrono")]
+    {
+        #[derive(Serialize, Deserialize)]
+        struct Chrono(#[serde(with = "crate::timestamp")] chrono::DateTime<chrono::Utc>);
+
+        let time = chrono::DateTime::from_timestamp(1_700_000_000, 5).unwrap();
+        let bytes = crate::to_bytes(&Chrono(time)).unwrap();
+        assert_eq!(crate::from_bytes::<Chrono>(&bytes).unwrap().0, time);
+
+        #[derive(Deserialize)]
+        struct RawChrono((i64, u32));
+        assert_eq!(crate::from_bytes::<RawChrono>(&bytes).unwrap().0, (1_700_000_000, 5));
+    }
+
+    #[cfg(feature = "time")]
+    {
+        #[derive(Serialize, Deserialize)]
+        struct Time(#[serde(with = "crate::timestamp")] time::OffsetDateTime);
+
+        let time = time::OffsetDateTime::from_unix_timestamp(-1_000).unwrap();
+        let bytes = crate::to_bytes(&Time(time)).unwrap();
+        assert_eq!(crate::from_bytes::<Time>(&bytes).unwrap().0, time);
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+#[test]
+fn test_debug_checks() {
+    use crate::ser::SerializeError;
+    use serde::ser::SerializeStruct;
+
+    struct Duplicate;
+
+    impl Serialize for Duplicate {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut s = serializer.serialize_struct("Duplicate", 2)?;
+            s.serialize_field("a", &1)?;
+            s.serialize_field("a", &2)?;
+            s.end()
+        }
+    }
+
+    assert!(matches!(crate::to_bytes(&Duplicate), Err(SerializeError::DuplicateField("a"))));
+
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new(&mut vec, 256)
+        .unwrap()
+        .with_sorted_maps(true);
+    let res = NoLenMap(vec![("key", 1), ("other", 2), ("key", 3)]).serialize(&mut ser);
+    assert!(matches!(res, Err(SerializeError::DuplicateMapKey)));
+
+    let res = crate::to_bytes(&NoLenMap(vec![("key", 1), ("other", 2), ("key", 3)]));
+    assert!(matches!(res, Err(SerializeError::DuplicateMapKey)));
+
+    let res = crate::to_bytes(&NoLenMap(vec![(1u32, 1), (2, 2), (1, 3)]));
+    assert!(matches!(res, Err(SerializeError::DuplicateMapKey)));
+}
+
+#[test]
+fn test_sniff() {
+    use crate::SniffInfo;
+
+    let bytes = crate::to_bytes(&"data").unwrap();
+    let info = crate::sniff(&bytes).unwrap();
+    assert_eq!(info, SniffInfo { version: FORMAT_VERSION, compression: None, dictionary: None, checksum: false, data_offset: 3 });
+    assert!(info.version_supported());
+
+    let compressed = crate::sniff(&[b's', b'd', 0x81, 2, 0]).unwrap();
+    assert_eq!(compressed, SniffInfo { version: 1, compression: Some(2), dictionary: None, checksum: false, data_offset: 4 });
+
+    let checksummed = crate::sniff(&[b's', b'd', 0x80, 0x80, 0]).unwrap();
+    assert_eq!(checksummed, SniffInfo { version: 0, compression: None, dictionary: None, checksum: true, data_offset: 4 });
+
+    let dictionary = crate::sniff(&[b's', b'd', 0x80, 3, 7, 0, 0, 0, 0]).unwrap();
+    assert_eq!(dictionary, SniffInfo { version: 0, compression: Some(3), dictionary: Some(7), checksum: false, data_offset: 8 });
+    assert!(crate::sniff(&[b's', b'd', 0x80, 3, 7, 0]).is_none());
+
+    assert!(crate::sniff(b"sd").is_none());
+    assert!(crate::sniff(b"sd\x80").is_none());
+    assert!(crate::sniff(b"{\"json\": true}").is_none());
+}
+
+#[cfg(feature = "checksum")]
+#[test]
+fn test_checksum() {
+    use crate::de::DeserializeError;
+
+    let data: Vec<(String, u32)> = (0..50).map(|i| (format!("key{}", i % 7), i)).collect();
+
+    let mut vec = vec![];
+    let mut ser = super::ser::Serializer::new_checksummed(&mut vec, 256).unwrap();
+    data.serialize(&mut ser).unwrap();
+    ser.finish().unwrap();
+
+    assert!(crate::sniff(&vec).unwrap().checksum);
+    assert_eq!(crate::from_bytes::<Vec<(String, u32)>>(&vec).unwrap(), data);
+    assert_eq!(crate::from_reader::<Vec<(String, u32)>, _>(vec.as_slice()).unwrap(), data);
+
+    let (_, read) = crate::from_bytes_prefix::<Vec<(String, u32)>>(&vec).unwrap();
+    assert_eq!(read, vec.len());
+
+    // Data after the document isn't part of the checksum
+    let followed = [vec.as_slice(), b"sd"].concat();
+    assert_eq!(crate::from_bytes::<Vec<(String, u32)>>(&followed).unwrap(), data);
+
+    // Flip a bit inside a string, which is still read successfully
+    let pos = vec.windows(4).position(|w| w == b"key3").unwrap();
+    vec[pos + 3] ^= 1;
+    let is_mismatch = |res| matches!(res, Err(DeserializeError::ChecksumMismatch { .. }));
+    assert!(is_mismatch(crate::from_bytes::<Vec<(String, u32)>>(&vec)));
+    assert!(is_mismatch(crate::from_reader::<Vec<(String, u32)>, _>(vec.as_slice())));
+    let is_mismatch = |res| matches!(res, Err(DeserializeError::ChecksumMismatch { .. }));
+    assert!(is_mismatch(crate::verify(vec.as_slice())));
+    assert!(is_mismatch(crate::verify_as::<Vec<(String, u32)>, _>(vec.as_slice())));
+}
+
+#[cfg(not(feature = "checksum"))]
+#[test]
+fn test_checksum_disabled() {
+    use crate::de::DeserializerInitError;
+
+    let checksummed = [b's', b'd', FORMAT_VERSION | crate::HEADER_FLAGS_BIT, crate::HEADER_CHECKSUM_FLAG, 0];
+    assert!(matches!(
+        super::de::Deserializer::new(checksummed.as_slice()),
+        Err(DeserializerInitError::UnsupportedChecksum)
+    ));
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+#[test]
+fn test_compression() {
+    use crate::{compress::Compression, de::DeserializerInitError};
+
+    let data: Vec<(String, u32)> = (0..500).map(|i| (format!("key{}", i % 7), i)).collect();
+    let plain = crate::to_bytes(&data).unwrap();
+
+    let compressions = [
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(0),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4,
+        #[cfg(feature = "parallel")]
+        Compression::ZstdParallel { level: 3, workers: 2 },
+    ];
+    for compression in compressions {
+        let mut vec = vec![];
+        let mut ser = super::ser::Serializer::new_compressed(&mut vec, 256, compression).unwrap();
+        data.serialize(&mut ser).unwrap();
+        ser.finish().unwrap();
+
+        assert!(vec.len() < plain.len(), "{compression:?}");
+        assert_eq!(crate::from_bytes::<Vec<(String, u32)>>(&vec).unwrap(), data);
+
+        // Documents placed one after another are read without consuming the next one
+        let mut second = vec![];
+        let mut ser = super::ser::Serializer::new_compressed(&mut second, 256, compression).unwrap();
+        "second".serialize(&mut ser).unwrap();
+        ser.finish().unwrap();
+
+        let both = [vec.as_slice(), second.as_slice()].concat();
+        let (read, len) = crate::from_bytes_prefix::<Vec<(String, u32)>>(&both).unwrap();
+        assert_eq!((read, len), (data.clone(), vec.len()), "{compression:?}");
+        assert_eq!(crate::from_bytes_prefix::<String>(&both[len..]).unwrap(), ("second".into(), second.len()));
+
+        let mut cur = io::Cursor::new(both.as_slice());
+        assert_eq!(crate::from_reader_mut::<Vec<(String, u32)>, _>(&mut cur).unwrap(), data);
+        assert_eq!(crate::from_reader_mut::<String, _>(&mut cur).unwrap(), "second");
+        assert_eq!(cur.position() as usize, both.len());
+    }
+
+    let unknown = [b's', b'd', 0x80, 0x7f];
+    assert!(matches!(
+        super::de::Deserializer::new(unknown.as_slice()),
+        Err(DeserializerInitError::UnsupportedCompression(0x7f))
+    ));
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_dictionary() {
+    use crate::{compress::ZstdDictionary, de::DeserializerInitError};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Save {
+        player: String,
+        level: u32,
+        inventory: Vec<String>,
+    }
+
+    let save = |i: u32| Save {
+        player: format!("player{i}"),
+        level: i,
+        inventory: vec!["sword".into(), "shield".into(), format!("potion of level {i}")],
+    };
+    let compress = |data: &Save, dictionary: Option<&ZstdDictionary>| {
+        let mut vec = vec![];
+        let mut ser = match dictionary {
+            Some(dictionary) => super::ser::Serializer::new_compressed_with_dictionary(&mut vec, 256, 3, dictionary),
+            None => super::ser::Serializer::new_compressed(&mut vec, 256, crate::compress::Compression::Zstd(3)),
+        }
+        .unwrap();
+        data.serialize(&mut ser).unwrap();
+        ser.finish().unwrap();
+        vec
+    };
+
+    // Any similar data works as a raw content dictionary, trained ones compress better
+    let dictionaries = [ZstdDictionary::new(7, crate::to_bytes(&save(0)).unwrap())];
+    let with_dictionary = compress(&save(1), Some(&dictionaries[0]));
+    assert!(with_dictionary.len() < compress(&save(1), None).len());
+    assert_eq!(crate::sniff(&with_dictionary).unwrap().dictionary, Some(7));
+
+    let mut de = super::de::Deserializer::new_with_dictionaries(with_dictionary.as_slice(), &dictionaries).unwrap();
+    assert_eq!(Save::deserialize(&mut de).unwrap(), save(1));
+    de.finish().unwrap();
+
+    // Documents without a dictionary are still read
+    let plain = crate::to_bytes(&save(2)).unwrap();
+    let mut de = super::de::Deserializer::new_with_dictionaries(plain.as_slice(), &dictionaries).unwrap();
+    assert_eq!(Save::deserialize(&mut de).unwrap(), save(2));
+
+    assert!(matches!(
+        super::de::Deserializer::new(with_dictionary.as_slice()),
+        Err(DeserializerInitError::UnknownDictionary(7))
+    ));
+    let other = ZstdDictionary::new(8, crate::to_bytes(&save(0)).unwrap());
+    assert!(matches!(
+        super::de::Deserializer::new_with_dictionaries(with_dictionary.as_slice(), &[other]),
+        Err(DeserializerInitError::UnknownDictionary(7))
+    ));
+}
+
+#[test]
+fn test_sets() {
+    use std::collections::{BTreeSet, HashSet};
+
+    let hash: HashSet<String> = ["a", "b", "c"].into_iter().map(String::from).collect();
+    let btree: BTreeSet<u32> = (0..50).collect();
+
+    let bytes = crate::to_bytes(&(&hash, &btree)).unwrap();
+    assert_eq!(crate::from_bytes::<(HashSet<String>, BTreeSet<u32>)>(&bytes).unwrap(), (hash, btree.clone()));
+
+    // Sets are written as sequences, so they can be read as Vec
+    let vec: Vec<u32> = crate::from_bytes(&crate::to_bytes(&btree).unwrap()).unwrap();
+    assert!(vec.into_iter().eq(btree));
+}
+
+#[test]
+fn test_unknown_variant() {
+    #[derive(Serialize)]
+    enum Renamed {
+        Bee,
+    }
+
+    let bytes = crate::to_bytes(&Renamed::Bee).unwrap();
+    let err = crate::from_bytes::<Enum>(&bytes).unwrap_err();
+    assert_eq!(err.to_string(), "Read unknown enum variant \"Bee\", expected one of: A, B, C, D");
+    assert!(matches!(
+        err,
+        crate::de::DeserializeError::UnknownVariant { variant, expected: ["A", "B", "C", "D"] } if variant == "Bee"
+    ));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async() {
+    use crate::aio::{AsyncReader, AsyncWriter};
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let (client, server) = tokio::io::duplex(16);
+
+        let write = async move {
+            let mut writer = AsyncWriter::new(client);
+            for i in 0..20u32 {
+                writer.write(&(i, format!("snapshot {i}"))).await.unwrap();
+            }
+        };
+
+        let read = async move {
+            let mut reader = AsyncReader::new(server);
+            let mut read = vec![];
+            while let Some(value) = reader.read::<(u32, String)>().await.unwrap() {
+                read.push(value);
+            }
+            read
+        };
+
+        let ((), read) = tokio::join!(write, read);
+        assert_eq!(read.len(), 20);
+        assert_eq!(read[19], (19, "snapshot 19".into()));
+
+        // length prefix with bits past the 64th in its 10th byte
+        let overlong: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        let res = AsyncReader::new(overlong).read::<u32>().await;
+        assert!(matches!(
+            res,
+            Err(crate::net::FrameError::InvalidLength(crate::varint::VarIntReadError::ValueTooBig))
+        ));
+
+        let endless: &[u8] = &[0xff; 11];
+        let res = AsyncReader::new(endless).read::<u32>().await;
+        assert!(matches!(
+            res,
+            Err(crate::net::FrameError::InvalidLength(crate::varint::VarIntReadError::ValueTooBig))
+        ));
+    });
+}
+
+#[test]
+fn test_measure() {
+    #[derive(Serialize)]
+    struct Player {
+        name: String,
+        inventory: Vec<u32>,
+    }
+
+    #[derive(Serialize)]
+    struct Save {
+        version: u32,
+        players: Vec<Player>,
+        settings: HashMap<String, Option<String>>,
+    }
+
+    let save = Save {
+        version: 1,
+        players: (0..4)
+            .map(|i| Player { name: format!("player{i}"), inventory: (0..100).collect() })
+            .collect(),
+        settings: [("lang".into(), Some("en".into()))].into_iter().collect(),
+    };
+    let bytes = crate::to_bytes(&save).unwrap();
+    let report = crate::measure::measure(&bytes).unwrap();
+    print!("{report}");
+
+    assert_eq!(report.get("").unwrap().bytes as usize, bytes.len() - 3);
+    assert_eq!(report.get("players[]").unwrap().count, 4);
+    assert_eq!(report.get("players[].inventory[]").unwrap().count, 400);
+    assert_eq!(report.get("settings[]").unwrap().count, 1);
+    assert_eq!(report.heaviest(3)[1].0, "players");
+    assert_eq!(report.heaviest(3)[2].0, "players[]");
+}
+
+#[test]
+#[cfg_attr(not(feature = "int128"), ignore = "uses u128")]
+fn test_value() {
+    use crate::value::{Value, VariantData};
+
+    let data = Struct {
+        values: HashMap::from_iter([(0, "zero".into()), (1, "one".into())]),
+        e: vec![
+            Enum::D { v: NoLenSerialize(vec![0, 5]) },
+            Enum::C("c".into(), 32, 64),
+            Enum::B,
+        ],
+        tup: (true, u128::MAX),
+    };
+    let bytes = crate::to_bytes(&data).unwrap();
+
+    let mut value: Value = crate::from_bytes(&bytes).unwrap();
+    assert_eq!(value, crate::to_value(&data).unwrap());
+    assert!(matches!(value.get("e"), Some(Value::Array(_))));
+
+    let bytes = crate::to_bytes(&value).unwrap();
+    assert_eq!(crate::from_bytes::<Struct>(&bytes).unwrap(), data);
+
+    let Some(Value::Array(variants)) = value.get_mut("e") else { panic!() };
+    variants.push(Value::Variant("A".into(), VariantData::Newtype(Box::new(Value::Int(-5)))));
+    let modified: Struct = crate::from_value(&value).unwrap();
+    assert_eq!(modified.e.last(), Some(&Enum::A(-5)));
+
+    let mut ser = crate::Serializer::new(vec![], 255).unwrap().with_compact_map_keys(true);
+    [(1u8, 'a'), (2, 'b')].into_iter().collect::<HashMap<_, _>>().serialize(&mut ser).unwrap();
+    let value: Value = crate::from_bytes(&ser.writer).unwrap();
+    let Value::Map(mut entries) = value else { panic!() };
+    entries.sort_by_key(|(k, _)| k.as_int());
+    assert_eq!(entries, [(Value::UInt(1), Value::Char('a')), (Value::UInt(2), Value::Char('b'))]);
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_arbitrary_value() {
+    use crate::value::Value;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    // Same check as the transcode fuzz target, over deterministic pseudo-random inputs
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..500 {
+        let data: Vec<u8> = (0..512)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let value = Value::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        let bytes = crate::to_bytes(&value).unwrap();
+        let read: Value = crate::from_bytes(&bytes).unwrap();
+        assert_eq!(crate::to_bytes(&read).unwrap(), bytes, "{value:?}");
+    }
+}
+
+#[test]
+fn test_borrowed_option() {
+    let owned = Some(String::from("value"));
+    let borrowed: Option<&String> = owned.as_ref();
+
+    let mut vec = vec![];
+    let mut ser = crate::Serializer::new(&mut vec, 255).unwrap();
+    ser.write_value(&borrowed).unwrap();
+    ser.write_value(&None::<&String>).unwrap();
+
+    assert_eq!(crate::to_bytes(&borrowed).unwrap(), crate::to_bytes(&owned).unwrap());
+    let read: Vec<Option<String>> = crate::read_docs(vec.as_slice()).unwrap().collect::<Result<_, _>>().unwrap();
+    assert_eq!(read, [owned, None]);
+}
+
+#[test]
+fn test_default_document() {
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Save {
+        level: u32,
+        name: String,
+    }
+
+    let empty = crate::empty_document::<Save>().unwrap();
+    assert_eq!(crate::from_bytes::<Save>(&empty).unwrap(), Save::default());
+
+    let path = std::env::temp_dir().join(format!("smoldata-default-{}", std::process::id()));
+    assert_eq!(crate::from_file_or_default::<Save>(&path).unwrap(), Save::default());
+
+    let save = Save { level: 3, name: "save".into() };
+    crate::to_file(&save, &path).unwrap();
+    let read = crate::from_file_or_default::<Save>(&path);
+    std::fs::write(&path, b"not a save").unwrap();
+    let corrupted = crate::from_file_or_default::<Save>(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(read.unwrap(), save);
+    assert!(corrupted.is_err());
+}
+
+#[test]
+fn test_lenient_string() {
+    use crate::value::LenientString;
+
+    #[derive(Serialize)]
+    struct Detail {
+        code: u32,
+        path: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct OldReport {
+        detail: Detail,
+        kind: Enum,
+    }
+
+    #[derive(Serialize)]
+    struct NewReport {
+        detail: &'static str,
+        kind: &'static str,
+    }
+
+    #[derive(Deserialize)]
+    struct Report {
+        detail: LenientString,
+        kind: LenientString,
+    }
+
+    let old = OldReport {
+        detail: Detail { code: 404, path: Some("/index".into()) },
+        kind: Enum::C("c".into(), 1, 2),
+    };
+    let report: Report = crate::from_bytes(&crate::to_bytes(&old).unwrap()).unwrap();
+    assert_eq!(report.detail.as_str(), r#"{ code: 404, path: Some("/index") }"#);
+    assert_eq!(report.kind.as_str(), r#"C("c", 1, 2)"#);
+
+    let new = NewReport { detail: "not found", kind: "C" };
+    let report: Report = crate::from_bytes(&crate::to_bytes(&new).unwrap()).unwrap();
+    assert_eq!(report.detail.as_str(), "not found");
+    assert_eq!(report.kind.as_str(), "C");
+}
+
+#[cfg(feature = "store")]
+#[test]
+fn test_file_store() {
+    use crate::store::FileStore;
+
+    let dir = std::env::temp_dir().join(format!("smoldata-store-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = FileStore::<Vec<u32>>::new(dir.join("save")).with_backups(2);
+
+    assert_eq!(store.load_or_default().unwrap(), Vec::<u32>::new());
+    for i in 1..=4 {
+        store.save(&(0..i).collect()).unwrap();
+    }
+
+    let loaded = store.load();
+    let backups: Vec<_> = (1..=3).map(|i| crate::from_file::<Vec<u32>>(store.backup_path(i)).ok()).collect();
+    std::fs::write(store.path(), b"corrupted").unwrap();
+    let recovered = store.load_or_backup();
+
+    // Failing backup rotation leaves the document and no temporary file behind
+    let broken = FileStore::<u32>::new(dir.join("broken")).with_backups(1);
+    broken.save(&1).unwrap();
+    std::fs::create_dir(broken.backup_path(1)).unwrap();
+    let broken_save = broken.save(&2);
+    let broken_loaded = broken.load();
+
+    // Documents of an older version are migrated on load and replaced on the next save
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Save {
+        version: u32,
+        items: Vec<u32>,
+    }
+    let migrated = FileStore::<Save>::new(store.path()).with_migration(|value| {
+        Ok(Save { version: 2, items: crate::from_value(&value)? })
+    });
+    std::fs::write(store.path(), crate::to_bytes(&vec![7u32, 8]).unwrap()).unwrap();
+    let migrated_loaded = migrated.load();
+    migrated.save(&Save { version: 2, items: vec![9] }).unwrap();
+    let migrated_saved = crate::from_file::<Save>(store.path());
+    let unmigrated = FileStore::<Save>::new(store.backup_path(1)).load();
+
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.unwrap(), [0, 1, 2, 3]);
+    assert_eq!(backups, [Some(vec![0, 1, 2]), Some(vec![0, 1]), None]);
+    assert_eq!(recovered.unwrap(), [0, 1, 2]);
+    assert!(broken_save.is_err());
+    assert_eq!(broken_loaded.unwrap(), 1);
+    assert_eq!(files, ["broken", "broken.bak1", "save", "save.bak1", "save.bak2"]);
+    assert_eq!(migrated_loaded.unwrap(), Save { version: 2, items: vec![7, 8] });
+    assert_eq!(migrated_saved.unwrap(), Save { version: 2, items: vec![9] });
+    assert!(unmigrated.is_err());
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn test_pod() {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Telemetry {
+        time: u32,
+        voltage: u16,
+        current: u16,
+    }
+
+    unsafe impl bytemuck::Zeroable for Telemetry {}
+    unsafe impl bytemuck::Pod for Telemetry {}
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Packet {
+        id: u8,
+
+        #[serde(with = "crate::pod")]
+        telemetry: Telemetry,
+    }
+
+    let packet = Packet { id: 1, telemetry: Telemetry { time: 1000, voltage: 33, current: 5 } };
+    let bytes = crate::to_bytes(&packet).unwrap();
+    assert_eq!(crate::from_bytes::<Packet>(&bytes).unwrap(), packet);
+
+    #[derive(Serialize)]
+    struct ShortPacket {
+        id: u8,
+        telemetry: crate::bytes::Bytes<'static>,
+    }
+
+    let short = crate::to_bytes(&ShortPacket { id: 1, telemetry: crate::bytes::Bytes(&[0; 4]) }).unwrap();
+    assert!(crate::from_bytes::<Packet>(&short).is_err());
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn test_pod_slices() {
+    use std::mem::MaybeUninit;
+
+    #[derive(Serialize)]
+    struct Mesh<'a> {
+        len: usize,
+
+        #[serde(serialize_with = "crate::pod::serialize_slice")]
+        vertices: &'a [[f32; 3]],
+    }
+
+    let vertices: Vec<[f32; 3]> = (0..1000).map(|i| [i as f32, 0.5, -1.0]).collect();
+    let bytes = crate::to_bytes(&Mesh { len: vertices.len(), vertices: &vertices }).unwrap();
+
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap();
+    let mut mesh = de.read_map().unwrap();
+    assert_eq!(mesh.read_entry::<String, usize>().unwrap(), Some(("len".into(), 1000)));
+    assert_eq!(mesh.read_key::<String>().unwrap().as_deref(), Some("vertices"));
+    drop(mesh);
+
+    let mut buf = vec![MaybeUninit::<[f32; 3]>::uninit(); 1000];
+    let read = crate::pod::read_into_uninit_slice(&mut de, &mut buf).unwrap();
+    assert_eq!(read, vertices.as_slice());
+
+    let short = crate::to_bytes(&crate::bytes::Bytes(&[0; 8])).unwrap();
+    let mut de = crate::Deserializer::new(short.as_slice()).unwrap();
+    let mut short = [0u32; 3];
+    assert!(crate::pod::read_into_slice(&mut de, &mut short).is_err());
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn test_nfc_strings() {
+    let composed = "caf\u{e9}";
+    let decomposed = "cafe\u{301}";
+    let map: HashMap<&str, u32> = [(composed, 1), (decomposed, 2)].into_iter().collect();
+    let bytes = crate::to_bytes(&(&map, decomposed)).unwrap();
+
+    let (map, string): (HashMap<String, u32>, String) = crate::from_bytes(&bytes).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(string, decomposed);
+
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap().with_nfc_strings(true);
+    let (map, string): (HashMap<String, u32>, String) = de.read_value().unwrap();
+    assert_eq!(map.keys().collect::<Vec<_>>(), [composed]);
+    assert_eq!(string, composed);
+}
+
+#[test]
+fn test_should_continue() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let data: Vec<u32> = (0..100).collect();
+    let bytes = crate::to_bytes(&data).unwrap();
+
+    let checks = Arc::new(AtomicUsize::new(0));
+    let counter = checks.clone();
+    let mut de = crate::Deserializer::new(bytes.as_slice())
+        .unwrap()
+        .with_should_continue(move || counter.fetch_add(1, Ordering::Relaxed) < 10);
+    let res = Vec::<u32>::deserialize(&mut de);
+    assert!(matches!(res, Err(crate::DeserializeError::TimedOut)));
+    assert_eq!(checks.load(Ordering::Relaxed), 11);
+
+    let past = std::time::Instant::now();
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap().with_deadline(past);
+    assert!(matches!(Vec::<u32>::deserialize(&mut de), Err(crate::DeserializeError::TimedOut)));
+
+    let future = std::time::Instant::now() + std::time::Duration::from_secs(60);
+    let mut de = crate::Deserializer::new(bytes.as_slice()).unwrap().with_deadline(future);
+    assert_eq!(Vec::<u32>::deserialize(&mut de).unwrap(), data);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json() {
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Doc {
+        name: String,
+        scores: HashMap<u32, f32>,
+        kind: Enum,
+        other: Vec<Enum>,
+        parent: Option<Box<Doc>>,
+    }
+
+    let doc = Doc {
+        name: "doc".into(),
+        scores: [(1, 0.5)].into_iter().collect(),
+        kind: Enum::B,
+        other: vec![Enum::A(-5), Enum::C("c".into(), 1, 2)],
+        parent: None,
+    };
+
+    let bytes = crate::to_bytes(&doc).unwrap();
+    let converted = crate::convert::to_json_value(bytes.as_slice()).unwrap();
+    assert_eq!(
+        converted,
+        json!({
+            "name": "doc",
+            "scores": { "1": 0.5 },
+            "kind": "B",
+            "other": [{ "A": -5 }, { "C": ["c", 1, 2] }],
+            "parent": null,
+        })
+    );
+
+    let mut written = vec![];
+    crate::convert::from_json_value(&json!({ "name": "json", "values": [1, -2, 3.5] }), &mut written).unwrap();
+    assert_eq!(
+        crate::convert::to_json_value(written.as_slice()).unwrap(),
+        json!({ "name": "json", "values": [1, -2, 3.5] })
+    );
+}
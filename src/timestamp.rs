@@ -0,0 +1,100 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de, Deserialize, Serialize};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Time point that can be written as a timestamp, see [`serialize`]
+pub trait Timestamp: Sized {
+    /// Whole seconds since the Unix epoch, rounded down, and nanoseconds after them
+    fn to_unix(&self) -> (i64, u32);
+
+    /// Time point from seconds since the Unix epoch and nanoseconds after them, None if it's out of range
+    fn from_unix(secs: i64, nanos: u32) -> Option<Self>;
+}
+
+/// Serialize a time point as a `(i64, u32)` tuple of whole seconds since the Unix epoch, rounded down,
+/// and nanoseconds after them, for use with `#[serde(with = "smoldata::timestamp")]`.<br>
+/// Time points before the epoch have negative seconds and positive nanoseconds, so `-0.25s` is `(-1, 750000000)`.
+/// This is the same as protobuf's `Timestamp`, so timestamps can be read by implementations in other languages
+pub fn serialize<T: Timestamp, S: serde::Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.to_unix().serialize(serializer)
+}
+
+/// Deserialize a time point written by [`serialize`], erroring if it's out of range of the type
+pub fn deserialize<'de, T: Timestamp, D: serde::Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    let (secs, nanos) = <(i64, u32)>::deserialize(deserializer)?;
+    if nanos >= NANOS_PER_SEC {
+        return Err(de::Error::custom(format_args!("Invalid timestamp nanoseconds {nanos}")));
+    }
+    T::from_unix(secs, nanos).ok_or_else(|| de::Error::custom(format_args!("Timestamp {secs}s is out of range")))
+}
+
+impl Timestamp for SystemTime {
+    fn to_unix(&self) -> (i64, u32) {
+        match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => (i64::try_from(since.as_secs()).unwrap_or(i64::MAX), since.subsec_nanos()),
+            Err(e) => {
+                let before = e.duration();
+                let secs = i64::try_from(before.as_secs()).map_or(i64::MIN, |s| -s);
+                match before.subsec_nanos() {
+                    0 => (secs, 0),
+                    nanos => (secs.saturating_sub(1), NANOS_PER_SEC - nanos),
+                }
+            }
+        }
+    }
+
+    fn from_unix(secs: i64, nanos: u32) -> Option<Self> {
+        let nanos = Duration::from_nanos(nanos.into());
+        match u64::try_from(secs) {
+            Ok(secs) => UNIX_EPOCH.checked_add(Duration::from_secs(secs))?.checked_add(nanos),
+            Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))?.checked_add(nanos),
+        }
+    }
+}
+
+/// Leap seconds are written as the last nanosecond of the previous second
+#[cfg(feature = "chrono")]
+impl Timestamp for chrono::DateTime<chrono::Utc> {
+    fn to_unix(&self) -> (i64, u32) {
+        (self.timestamp(), self.timestamp_subsec_nanos().min(NANOS_PER_SEC - 1))
+    }
+
+    fn from_unix(secs: i64, nanos: u32) -> Option<Self> {
+        Self::from_timestamp(secs, nanos)
+    }
+}
+
+/// Offset is not written, read time points are in UTC
+#[cfg(feature = "time")]
+impl Timestamp for time::OffsetDateTime {
+    fn to_unix(&self) -> (i64, u32) {
+        (self.unix_timestamp(), self.nanosecond())
+    }
+
+    fn from_unix(secs: i64, nanos: u32) -> Option<Self> {
+        Self::from_unix_timestamp(secs).ok()?.replace_nanosecond(nanos).ok()
+    }
+}
+
+/// Serialize a `Duration` as a `(u64, u32)` tuple of whole seconds and nanoseconds after them,
+/// for use with `#[serde(with = "smoldata::timestamp::duration")]`.<br>
+/// More compact than serde's encoding of `Duration`, which is a struct with named fields
+pub mod duration {
+    use std::time::Duration;
+
+    use serde::{de, Deserialize, Serialize};
+
+    pub fn serialize<S: serde::Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        (value.as_secs(), value.subsec_nanos()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let (secs, nanos) = <(u64, u32)>::deserialize(deserializer)?;
+        if nanos >= super::NANOS_PER_SEC {
+            return Err(de::Error::custom(format_args!("Invalid duration nanoseconds {nanos}")));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
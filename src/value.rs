@@ -0,0 +1,538 @@
+use std::{fmt, io, ops::Deref};
+
+use serde::{de::Visitor, Deserialize, Serialize, Serializer as _};
+
+use crate::{
+    de::{DeserializeError, Deserializer},
+    ser::SerializeError,
+    tag::{OptionTag, StructType, TypeTag},
+    varint, RawValue, Serializer,
+};
+
+/// Any value of the smoldata data model, for reading documents of unknown types, inspecting or modifying them and writing them back.<br>
+/// Integers and floats are widened, so their exact width isn't preserved, but they're still readable as the original types
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i128),
+    UInt(u128),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<Value>>),
+    Newtype(Box<Value>),
+    Struct(Vec<(String, Value)>),
+    Variant(String, VariantData),
+    Array(Vec<Value>),
+    Tuple(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+/// Data of an enum variant value
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantData {
+    Unit,
+    Newtype(Box<Value>),
+    Tuple(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Read a single value from a deserializer
+    pub fn read<R: io::Read>(de: &mut Deserializer<R>) -> Result<Self, DeserializeError> {
+        let value = match de.peek_tag()? {
+            TypeTag::Option(OptionTag::Some) => {
+                de.peek_tag_consume();
+                Value::Option(Some(Box::new(Self::read(de)?)))
+            }
+            TypeTag::Struct(StructType::Newtype) => {
+                de.peek_tag_consume();
+                Value::Newtype(Box::new(Self::read(de)?))
+            }
+            TypeTag::Struct(StructType::Struct) => {
+                de.peek_tag_consume();
+                Value::Struct(Self::read_fields(de)?)
+            }
+            TypeTag::Struct(StructType::Tuple) | TypeTag::Tuple => {
+                de.peek_tag_consume();
+                Value::Tuple(Self::read_elements(de)?)
+            }
+            TypeTag::Seq { has_length } => {
+                de.peek_tag_consume();
+                if has_length {
+                    Value::Array(Self::read_elements(de)?)
+                } else {
+                    let mut values = vec![];
+                    while !matches!(de.peek_tag()?, TypeTag::End) {
+                        values.push(Self::read(de)?);
+                    }
+                    de.peek_tag_consume();
+                    Value::Array(values)
+                }
+            }
+            TypeTag::Map { .. } | TypeTag::PrimitiveKeyMap => {
+                let mut map = de.read_map()?;
+                let mut entries = vec![];
+                while let Some(key) = map.read_key::<Value>()? {
+                    entries.push((key, map.read_value()?));
+                }
+                Value::Map(entries)
+            }
+            TypeTag::EnumVariant { ty, str } => {
+                de.peek_tag_consume();
+                let name = de.read_str(str)?.to_string();
+                let data = match ty {
+                    StructType::Unit => VariantData::Unit,
+                    StructType::Newtype => VariantData::Newtype(Box::new(Self::read(de)?)),
+                    StructType::Tuple => VariantData::Tuple(Self::read_elements(de)?),
+                    StructType::Struct => VariantData::Struct(Self::read_fields(de)?),
+                };
+                Value::Variant(name, data)
+            }
+            TypeTag::End => return Err(DeserializeError::ReadEnd),
+            _ => serde::Deserializer::deserialize_any(&mut *de, ScalarVisitor)?,
+        };
+        Ok(value)
+    }
+
+    fn read_elements<R: io::Read>(de: &mut Deserializer<R>) -> Result<Vec<Value>, DeserializeError> {
+        let len = varint::read_length(&mut de.reader)?;
+        (0..len).map(|_| Self::read(de)).collect()
+    }
+
+    fn read_fields<R: io::Read>(de: &mut Deserializer<R>) -> Result<Vec<(String, Value)>, DeserializeError> {
+        let len = varint::read_length(&mut de.reader)?;
+        (0..len)
+            .map(|_| {
+                let name = match de.read_tag()? {
+                    TypeTag::Str(str) => de.read_str(str)?.to_string(),
+                    tag => return Err(DeserializeError::Expected("field name", tag.into())),
+                };
+                Ok((name, Self::read(de)?))
+            })
+            .collect()
+    }
+
+    /// Write the value into a serializer
+    pub fn write<W: io::Write>(&self, ser: &mut Serializer<W>) -> Result<(), SerializeError> {
+        match self {
+            Value::Unit => ser.serialize_unit(),
+            Value::Bool(v) => ser.serialize_bool(*v),
+            Value::Int(v) => match i64::try_from(*v) {
+                Ok(v) => ser.serialize_i64(v),
+                Err(_) => ser.serialize_i128(*v),
+            },
+            Value::UInt(v) => match u64::try_from(*v) {
+                Ok(v) => ser.serialize_u64(v),
+                Err(_) => ser.serialize_u128(*v),
+            },
+            Value::Float(v) => ser.serialize_f64(*v),
+            Value::Char(v) => ser.serialize_char(*v),
+            Value::Str(v) => ser.serialize_str(v),
+            Value::Bytes(v) => ser.serialize_bytes(v),
+            Value::Option(None) => ser.serialize_none(),
+            Value::Option(Some(v)) => {
+                ser.write_tag(TypeTag::Option(OptionTag::Some))?;
+                v.write(ser)
+            }
+            Value::Newtype(v) => {
+                ser.write_tag(TypeTag::Struct(StructType::Newtype))?;
+                v.write(ser)
+            }
+            Value::Struct(fields) => {
+                ser.write_tag(TypeTag::Struct(StructType::Struct))?;
+                Self::write_fields(fields, ser)
+            }
+            Value::Variant(name, data) => {
+                let ty = match data {
+                    VariantData::Unit => StructType::Unit,
+                    VariantData::Newtype(_) => StructType::Newtype,
+                    VariantData::Tuple(_) => StructType::Tuple,
+                    VariantData::Struct(_) => StructType::Struct,
+                };
+                ser.write_cached_str(name.as_str(), &|str| TypeTag::EnumVariant { ty, str })?;
+                match data {
+                    VariantData::Unit => Ok(()),
+                    VariantData::Newtype(v) => v.write(ser),
+                    VariantData::Tuple(values) => Self::write_elements(values, ser),
+                    VariantData::Struct(fields) => Self::write_fields(fields, ser),
+                }
+            }
+            Value::Array(values) => {
+                ser.write_tag(TypeTag::Seq { has_length: true })?;
+                Self::write_elements(values, ser)
+            }
+            Value::Tuple(values) => {
+                ser.write_tag(TypeTag::Tuple)?;
+                Self::write_elements(values, ser)
+            }
+            Value::Map(entries) => {
+                ser.write_tag(TypeTag::Map { has_length: true })?;
+                varint::write_unsigned_varint(&mut ser.writer, entries.len())?;
+                for (key, value) in entries {
+                    key.write(ser)?;
+                    value.write(ser)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_elements<W: io::Write>(values: &[Value], ser: &mut Serializer<W>) -> Result<(), SerializeError> {
+        varint::write_unsigned_varint(&mut ser.writer, values.len())?;
+        values.iter().try_for_each(|v| v.write(ser))
+    }
+
+    fn write_fields<W: io::Write>(fields: &[(String, Value)], ser: &mut Serializer<W>) -> Result<(), SerializeError> {
+        varint::write_unsigned_varint(&mut ser.writer, fields.len())?;
+        for (name, value) in fields {
+            ser.write_cached_str(name.as_str(), &TypeTag::Str)?;
+            value.write(ser).map_err(|e| e.in_field(name))?;
+        }
+        Ok(())
+    }
+
+    pub fn from_raw(raw: &RawValue) -> Result<Self, DeserializeError> {
+        Self::read(&mut raw.create_deserializer())
+    }
+
+    pub fn to_raw(&self) -> Result<RawValue, SerializeError> {
+        let mut buf = vec![];
+        self.write(&mut Serializer::new_bare(&mut buf, 256))?;
+        Ok(RawValue::from_bytes(buf.into_boxed_slice()))
+    }
+
+    /// Field of a struct or struct variant by its name
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields()?.iter().find(|(name, _)| name == field).map(|(_, v)| v)
+    }
+
+    /// Mutable field of a struct or struct variant by its name
+    pub fn get_mut(&mut self, field: &str) -> Option<&mut Value> {
+        let fields = match self {
+            Value::Struct(fields) | Value::Variant(_, VariantData::Struct(fields)) => fields,
+            _ => return None,
+        };
+        fields.iter_mut().find(|(name, _)| name == field).map(|(_, v)| v)
+    }
+
+    /// Fields of a struct or struct variant
+    pub fn fields(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Struct(fields) | Value::Variant(_, VariantData::Struct(fields)) => Some(fields),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Integer value, if it fits into `i128`
+    pub fn as_int(&self) -> Option<i128> {
+        match self {
+            Value::Int(v) => Some(*v),
+            Value::UInt(v) => i128::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Turn non-negative signed integers into unsigned ones, in this value and all values inside of it,
+    /// so equal integers compare equal regardless of their signedness
+    pub(crate) fn normalize_ints(&mut self) {
+        match self {
+            Value::Int(v) => {
+                if let Ok(v) = u128::try_from(*v) {
+                    *self = Value::UInt(v);
+                }
+            }
+            Value::Option(Some(v)) | Value::Newtype(v) | Value::Variant(_, VariantData::Newtype(v)) => v.normalize_ints(),
+            Value::Struct(fields) | Value::Variant(_, VariantData::Struct(fields)) => {
+                fields.iter_mut().for_each(|(_, v)| v.normalize_ints())
+            }
+            Value::Array(values) | Value::Tuple(values) | Value::Variant(_, VariantData::Tuple(values)) => {
+                values.iter_mut().for_each(Value::normalize_ints)
+            }
+            Value::Map(entries) => entries.iter_mut().for_each(|(k, v)| {
+                k.normalize_ints();
+                v.normalize_ints();
+            }),
+            _ => {}
+        }
+    }
+}
+
+/// Single line, Rust-like representation of the value, such as `Variant { field: [1, 2], name: "name" }`
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => f.write_str("()"),
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::Int(v) => write!(f, "{v}"),
+            Value::UInt(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{v:?}"),
+            Value::Char(v) => write!(f, "{v:?}"),
+            Value::Str(v) => write!(f, "{v:?}"),
+            Value::Bytes(v) => write!(f, "b{:?}", v.escape_ascii().to_string()),
+            Value::Option(None) => f.write_str("None"),
+            Value::Option(Some(v)) => write!(f, "Some({v})"),
+            Value::Newtype(v) => write!(f, "({v})"),
+            Value::Struct(fields) => write_fields(f, fields),
+            Value::Variant(name, data) => {
+                f.write_str(name)?;
+                match data {
+                    VariantData::Unit => Ok(()),
+                    VariantData::Newtype(v) => write!(f, "({v})"),
+                    VariantData::Tuple(values) => write_list(f, "(", values, ")"),
+                    VariantData::Struct(fields) => {
+                        f.write_str(" ")?;
+                        write_fields(f, fields)
+                    }
+                }
+            }
+            Value::Array(values) => write_list(f, "[", values, "]"),
+            Value::Tuple(values) => write_list(f, "(", values, ")"),
+            Value::Map(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{sep}{key}: {value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, open: &str, values: &[Value], close: &str) -> fmt::Result {
+    f.write_str(open)?;
+    for (i, value) in values.iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        write!(f, "{sep}{value}")?;
+    }
+    f.write_str(close)
+}
+
+fn write_fields(f: &mut fmt::Formatter<'_>, fields: &[(String, Value)]) -> fmt::Result {
+    if fields.is_empty() {
+        return f.write_str("{}");
+    }
+    f.write_str("{ ")?;
+    for (i, (name, value)) in fields.iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        write!(f, "{sep}{name}: {value}")?;
+    }
+    f.write_str(" }")
+}
+
+/// Serialized through `RawValue`, so only supported by smoldata serializers
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_raw()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Deserialized through `RawValue`, so only supported by smoldata deserializers
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = RawValue::deserialize(deserializer)?;
+        Value::from_raw(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Max nesting of generated values, deeper values only contain scalars
+#[cfg(feature = "arbitrary")]
+const ARBITRARY_MAX_DEPTH: usize = 8;
+
+/// Generated values are writable by [`Value::write`], for fuzzing transcoding of documents
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Self::arbitrary_nested(u, 0)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl Value {
+    fn arbitrary_nested(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Self> {
+        let kinds = match depth < ARBITRARY_MAX_DEPTH {
+            true => 15,
+            false => 8,
+        };
+        let value = match u.choose_index(kinds)? {
+            0 => Value::Unit,
+            1 => Value::Bool(u.arbitrary()?),
+            2 => Value::Int(u.arbitrary()?),
+            3 => Value::UInt(u.arbitrary()?),
+            4 => Value::Float(u.arbitrary()?),
+            5 => Value::Char(u.arbitrary()?),
+            6 => Value::Str(u.arbitrary()?),
+            7 => Value::Bytes(u.arbitrary()?),
+            8 => match u.arbitrary()? {
+                true => Value::Option(Some(Box::new(Self::arbitrary_nested(u, depth + 1)?))),
+                false => Value::Option(None),
+            },
+            9 => Value::Newtype(Box::new(Self::arbitrary_nested(u, depth + 1)?)),
+            10 => Value::Struct(Self::arbitrary_fields(u, depth)?),
+            11 => {
+                let data = match u.choose_index(4)? {
+                    0 => VariantData::Unit,
+                    1 => VariantData::Newtype(Box::new(Self::arbitrary_nested(u, depth + 1)?)),
+                    2 => VariantData::Tuple(Self::arbitrary_elements(u, depth)?),
+                    _ => VariantData::Struct(Self::arbitrary_fields(u, depth)?),
+                };
+                Value::Variant(u.arbitrary()?, data)
+            }
+            12 => Value::Array(Self::arbitrary_elements(u, depth)?),
+            13 => Value::Tuple(Self::arbitrary_elements(u, depth)?),
+            _ => Value::Map(arbitrary_list(u, |u| {
+                Ok((Self::arbitrary_nested(u, depth + 1)?, Self::arbitrary_nested(u, depth + 1)?))
+            })?),
+        };
+        Ok(value)
+    }
+
+    fn arbitrary_elements(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Vec<Value>> {
+        arbitrary_list(u, |u| Self::arbitrary_nested(u, depth + 1))
+    }
+
+    fn arbitrary_fields(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Vec<(String, Value)>> {
+        arbitrary_list(u, |u| Ok((u.arbitrary()?, Self::arbitrary_nested(u, depth + 1)?)))
+    }
+}
+
+/// Generate elements while a generated flag is set, ending once data runs out
+#[cfg(feature = "arbitrary")]
+fn arbitrary_list<'a, T>(
+    u: &mut arbitrary::Unstructured<'a>,
+    mut element: impl FnMut(&mut arbitrary::Unstructured<'a>) -> arbitrary::Result<T>,
+) -> arbitrary::Result<Vec<T>> {
+    let mut list = vec![];
+    while u.arbitrary()? {
+        list.push(element(u)?);
+    }
+    Ok(list)
+}
+
+/// String read from either a string or any other value, which is formatted with [`Value`]'s `Display`.<br>
+/// For diagnostic fields, such as error messages, whose type may change between versions. Always written as a string.<br>
+/// Like `Value`, only readable by smoldata deserializers
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LenientString(pub String);
+
+impl Deref for LenientString {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl From<String> for LenientString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl fmt::Display for LenientString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for LenientString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for LenientString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match Value::deserialize(deserializer)? {
+            Value::Str(str) => Ok(Self(str)),
+            value => Ok(Self(value.to_string())),
+        }
+    }
+}
+
+/// Visitor of values without nested values
+struct ScalarVisitor;
+
+impl Visitor<'_> for ScalarVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v.into()))
+    }
+
+    fn visit_i128<E: serde::de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::UInt(v.into()))
+    }
+
+    fn visit_u128<E: serde::de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::UInt(v))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_char<E: serde::de::Error>(self, v: char) -> Result<Value, E> {
+        Ok(Value::Char(v))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Str(v.into()))
+    }
+
+    fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::Str(v))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.into()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Option(None))
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Unit)
+    }
+}
@@ -11,13 +11,16 @@ pub enum Sign {
 }
 
 impl Sign {
+    #[inline]
     pub fn is_positive(self) -> bool {
         matches!(self, Self::Positive)
     }
+    #[inline]
     pub fn is_negative(self) -> bool {
         matches!(self, Self::Negative)
     }
 
+    #[inline]
     pub fn into_neg_bit(self) -> bool {
         match self {
             Sign::Positive => false,
@@ -25,6 +28,7 @@ impl Sign {
         }
     }
 
+    #[inline]
     pub fn from_neg_bit(bit: bool) -> Self {
         match bit {
             false => Sign::Positive,
@@ -43,6 +47,9 @@ pub enum VarIntReadError {
 
     #[error("Read invalid signed value")]
     InvalidSignedValue,
+
+    #[error("Read length {0} is too big for this platform")]
+    LengthTooLargeForPlatform(u64),
 }
 
 pub trait UnsignedInt: Copy + Shr<u32, Output = Self> + BitOr<Output = Self> {
@@ -172,6 +179,11 @@ pub fn read_unsigned_varint<I: UnsignedInt, R: io::Read>(
             .checked_shl(shift)
             .ok_or(VarIntReadError::ValueTooBig)?;
 
+        // checked_shl only checks the shift amount, bits shifted out of the value are lost silently
+        if (shifted_data >> shift).into_u8_bits_trimmed() != data {
+            return Err(VarIntReadError::ValueTooBig);
+        }
+
         value = value | shifted_data;
 
         if !more {
@@ -184,6 +196,18 @@ pub fn read_unsigned_varint<I: UnsignedInt, R: io::Read>(
     Ok(value)
 }
 
+/// Read a varint encoded length.<br>
+/// Lengths are read as `u64`, so lengths written on 64-bit platforms fail clearly on smaller ones
+pub fn read_length<R: io::Read>(reader: R) -> Result<usize, VarIntReadError> {
+    read_length_as(reader)
+}
+
+/// Read a varint encoded length into a platform-sized integer, `usize` unless testing other platforms
+fn read_length_as<L: TryFrom<u64>, R: io::Read>(reader: R) -> Result<L, VarIntReadError> {
+    let len: u64 = read_unsigned_varint(reader)?;
+    L::try_from(len).map_err(|_| VarIntReadError::LengthTooLargeForPlatform(len))
+}
+
 pub fn read_signed_varint<I: SignedInt, R: io::Read>(reader: R) -> Result<I, VarIntReadError> {
     let (value, sign) = read_varint_with_sign(reader)?;
     I::from_split_sign(value, sign).ok_or(VarIntReadError::InvalidSignedValue)
@@ -221,6 +245,11 @@ pub fn read_varint_with_sign<I: UnsignedInt, R: io::Read>(
             .checked_shl(shift)
             .ok_or(VarIntReadError::ValueTooBig)?;
 
+        // checked_shl only checks the shift amount, bits shifted out of the value are lost silently
+        if (shifted_data >> shift).into_u8_bits_trimmed() != data {
+            return Err(VarIntReadError::ValueTooBig);
+        }
+
         value = value | shifted_data;
 
         if !more {
@@ -307,6 +336,64 @@ pub fn copy_varint<S: io::Read, D: io::Write>(src: &mut S, dst: &mut D) -> Resul
     Ok(())
 }
 
+/// Decode an unsigned varint from the start of `bytes`, returning its value and length in bytes.<br>
+/// None if the varint is truncated or too big for `u128`
+pub fn decode(bytes: &[u8]) -> Option<(u128, usize)> {
+    decode_bits(bytes, 7)
+}
+
+/// Decode a signed varint from the start of `bytes`, returning its magnitude, sign and length in bytes
+pub fn decode_with_sign(bytes: &[u8]) -> Option<(u128, Sign, usize)> {
+    let (value, len) = decode_bits(bytes, 6)?;
+    Some((value, Sign::from_neg_bit(bytes[0] & 0b01000000 != 0), len))
+}
+
+/// Decode consecutive unsigned varints, such as the data at an offset where reading failed.<br>
+/// Stops at the end of data or at a varint that can't be decoded
+pub fn decode_all(mut bytes: &[u8]) -> Vec<(u128, usize)> {
+    let mut values = vec![];
+    while let Some((value, len)) = decode(bytes) {
+        values.push((value, len));
+        bytes = &bytes[len..];
+    }
+    values
+}
+
+/// Decode consecutive signed varints, stopping at the end of data or at a varint that can't be decoded or doesn't fit `i128`
+pub fn decode_all_signed(mut bytes: &[u8]) -> Vec<(i128, usize)> {
+    let mut values = vec![];
+    while let Some((value, sign, len)) = decode_with_sign(bytes) {
+        let value = match sign {
+            Sign::Positive => i128::try_from(value).ok(),
+            Sign::Negative if value == 0 => None,
+            Sign::Negative => 0i128.checked_sub_unsigned(value),
+        };
+        let Some(value) = value else { break };
+        values.push((value, len));
+        bytes = &bytes[len..];
+    }
+    values
+}
+
+/// Decode a varint with `first_bits` bits of data in its first byte and 7 in the rest
+fn decode_bits(bytes: &[u8], first_bits: u32) -> Option<(u128, usize)> {
+    let mut value = 0u128;
+    let mut shift = 0u32;
+    for (i, &byte) in bytes.iter().enumerate() {
+        let bits = if i == 0 { first_bits } else { 7 };
+        let data = (byte & ((1 << bits) - 1)) as u128;
+        if data != 0 && data.leading_zeros() < shift {
+            return None;
+        }
+        value |= data << shift.min(127);
+
+        if byte & 0b10000000 == 0 {
+            return Some((value, i + 1));
+        }
+        shift = shift.saturating_add(bits);
+    }
+    None
+}
 
 macro_rules! impl_varint_primitives {
     ($($signed:ident:$unsigned:ident),*) => {
@@ -316,18 +403,22 @@ macro_rules! impl_varint_primitives {
             impl UnsignedInt for $unsigned {
                 const ZERO: Self = 0;
 
+                #[inline]
                 fn is_zero(self) -> bool {
                     self == 0
                 }
 
+                #[inline]
                 fn into_u8_bits_trimmed(self) -> u8 {
                     (self & 0xff) as u8
                 }
 
+                #[inline]
                 fn from_u8_bits(val: u8) -> Self {
                     val as Self
                 }
 
+                #[inline]
                 fn checked_shl(self, shift: u32) -> Option<Self> {
                     $unsigned::checked_shl(self, shift)
                 }
@@ -336,6 +427,7 @@ macro_rules! impl_varint_primitives {
             impl SignedInt for $signed {
                 type Unsigned = $unsigned;
 
+                #[inline]
                 fn into_split_sign(self) -> (Self::Unsigned, Sign) {
                     if self >= 0 {
                         (self as $unsigned, Sign::Positive)
@@ -344,6 +436,7 @@ macro_rules! impl_varint_primitives {
                     }
                 }
 
+                #[inline]
                 fn from_split_sign(val: Self::Unsigned, sign: Sign) -> Option<Self> {
                     if sign.is_positive() {
                         val.try_into().ok()
@@ -361,7 +454,10 @@ macro_rules! impl_varint_primitives {
     };
 }
 
-impl_varint_primitives!(i8:u8, i16:u16, i32:u32, i64:u64, i128:u128, isize:usize);
+impl_varint_primitives!(i8:u8, i16:u16, i32:u32, i64:u64, isize:usize);
+
+#[cfg(feature = "int128")]
+impl_varint_primitives!(i128:u128);
 
 #[allow(clippy::unusual_byte_groupings)]
 #[cfg(test)]
@@ -432,7 +528,7 @@ mod tests {
 
             let mut cur = io::Cursor::new(&vec);
 
-            let read = read_signed_varint(&mut cur).unwrap();
+            let read: i64 = read_signed_varint(&mut cur).unwrap();
 
             assert_eq!(val, read);
         }
@@ -456,6 +552,18 @@ mod tests {
 
         assert!(matches!(res, Err(VarIntReadError::ValueTooBig)));
 
+        // 10th byte carries bits past the 64th
+        let overlong = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        let res = read_unsigned_varint::<u64, _>(io::Cursor::new(&overlong));
+        assert!(matches!(res, Err(VarIntReadError::ValueTooBig)));
+
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let res = read_unsigned_varint::<u64, _>(io::Cursor::new(&max));
+        assert_eq!(res.unwrap(), u64::MAX);
+
+        let res = read_unsigned_varint::<u8, _>(io::Cursor::new(&[0xff, 0x02]));
+        assert!(matches!(res, Err(VarIntReadError::ValueTooBig)));
+
         let neg_zero = [0b01000000];
         let cur = io::Cursor::new(&neg_zero);
 
@@ -490,4 +598,40 @@ mod tests {
 
         assert_eq!(bit_value, value, "{bit_value:x} != {value:x}");
     }
+
+    #[test]
+    fn test_decode_all() {
+        let mut vec = vec![];
+        write_unsigned_varint(&mut vec, 300u32).unwrap();
+        write_unsigned_varint(&mut vec, u64::MAX).unwrap();
+        write_unsigned_varint(&mut vec, 0u8).unwrap();
+        assert_eq!(decode_all(&vec), vec![(300, 2), (u64::MAX as u128, 10), (0, 1)]);
+
+        vec.push(0x80);
+        assert_eq!(decode_all(&vec).len(), 3);
+        assert_eq!(decode_all(&[0xff; 20]), vec![]);
+
+        let mut vec = vec![];
+        write_signed_varint(&mut vec, -5i32).unwrap();
+        write_signed_varint(&mut vec, i64::MIN).unwrap();
+        write_signed_varint(&mut vec, 100i16).unwrap();
+        assert_eq!(decode_all_signed(&vec), vec![(-5, 1), (i64::MIN as i128, 10), (100, 2)]);
+        assert_eq!(decode_with_sign(&vec), Some((5, Sign::Negative, 1)));
+
+        // Negative zero
+        assert_eq!(decode_all_signed(&[0x40]), vec![]);
+    }
+
+    #[test]
+    fn test_length_too_large() {
+        let mut vec = vec![];
+        let len = u32::MAX as u64 + 1;
+        write_unsigned_varint(&mut vec, len).unwrap();
+
+        // u32 stands in for usize of 32-bit platforms
+        let res = read_length_as::<u32, _>(vec.as_slice());
+        assert!(matches!(res, Err(VarIntReadError::LengthTooLargeForPlatform(l)) if l == len));
+
+        assert_eq!(read_length_as::<u64, _>(vec.as_slice()).unwrap(), len);
+    }
 }
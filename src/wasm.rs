@@ -0,0 +1,31 @@
+use wasm_bindgen::prelude::*;
+
+/// Check that bytes contain a single well-formed document, see [`crate::verify`]
+#[wasm_bindgen]
+pub fn verify(bytes: &[u8]) -> Result<(), JsError> {
+    Ok(crate::verify(bytes)?)
+}
+
+/// Read a document and format it as text, see [`crate::value::Value`]
+#[wasm_bindgen(js_name = toText)]
+pub fn to_text(bytes: &[u8]) -> Result<String, JsError> {
+    let mut de = crate::Deserializer::new(bytes)?;
+    Ok(crate::value::Value::read(&mut de)?.to_string())
+}
+
+/// Read a document and convert it to a JSON string, for reading it in JS with `JSON.parse`, see [`crate::convert::to_json_value`]
+#[cfg(feature = "json")]
+#[wasm_bindgen(js_name = toJson)]
+pub fn to_json(bytes: &[u8]) -> Result<String, JsError> {
+    Ok(crate::convert::to_json_value(bytes)?.to_string())
+}
+
+/// Write a JSON string as a document, see [`crate::convert::from_json_value`]
+#[cfg(feature = "json")]
+#[wasm_bindgen(js_name = fromJson)]
+pub fn from_json(json: &str) -> Result<Vec<u8>, JsError> {
+    let json: serde_json::Value = serde_json::from_str(json)?;
+    let mut bytes = vec![];
+    crate::convert::from_json_value(&json, &mut bytes)?;
+    Ok(bytes)
+}